@@ -0,0 +1,240 @@
+use ayaka_runtime::{anyhow::Result, *};
+use clap::ValueEnum;
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CoverageFormat {
+    Json,
+    Html,
+}
+
+pub fn read_coverage(path: impl AsRef<Path>) -> Result<Coverage> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Merge the coverage into the file.
+/// The file is created if not exists.
+pub fn merge_coverage_file(path: impl AsRef<Path>, coverage: &Coverage) -> Result<()> {
+    let path = path.as_ref();
+    let mut data = if path.exists() {
+        read_coverage(path)?
+    } else {
+        Coverage::default()
+    };
+    data.merge(coverage);
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(writer, &data)?;
+    Ok(())
+}
+
+fn unvisited_ranges(unvisited: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &act in unvisited {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == act => *end = act,
+            _ => ranges.push((act, act)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn print_report(report: &CoverageReport) {
+    for para in &report.paras {
+        print!(
+            "{}/{}: {:.1}% ({}/{})",
+            para.base_para,
+            para.para,
+            para.percent(),
+            para.visited(),
+            para.total
+        );
+        if para.unvisited.is_empty() {
+            println!();
+        } else {
+            println!(", unvisited: {}", unvisited_ranges(&para.unvisited));
+        }
+    }
+    println!(
+        "Total: {:.1}% ({}/{})",
+        report.percent(),
+        report.visited(),
+        report.total()
+    );
+}
+
+fn escape_html(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+fn render_html(title: &str, report: &CoverageReport) -> Result<String> {
+    let title = escape_html(title);
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>")?;
+    writeln!(html, "<head>")?;
+    writeln!(html, "<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>Coverage of {title}</title>")?;
+    writeln!(
+        html,
+        "<style>table {{ border-collapse: collapse; }} td, th {{ border: 1px solid #999; padding: 2px 8px; }} .full {{ background: #cfc; }} .partial {{ background: #ffc; }} .none {{ background: #fcc; }}</style>"
+    )?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body>")?;
+    writeln!(html, "<h1>Coverage of {title}</h1>")?;
+    writeln!(
+        html,
+        "<p>Total: {:.1}% ({}/{})</p>",
+        report.percent(),
+        report.visited(),
+        report.total()
+    )?;
+    writeln!(html, "<table>")?;
+    writeln!(
+        html,
+        "<tr><th>File</th><th>Paragraph</th><th>Coverage</th><th>Lines</th><th>Unvisited</th></tr>"
+    )?;
+    for para in &report.paras {
+        let class = if para.unvisited.is_empty() {
+            "full"
+        } else if para.visited() > 0 {
+            "partial"
+        } else {
+            "none"
+        };
+        writeln!(
+            html,
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{:.1}%</td><td>{}/{}</td><td>{}</td></tr>",
+            class,
+            escape_html(&para.base_para),
+            escape_html(&para.para),
+            para.percent(),
+            para.visited(),
+            para.total,
+            unvisited_ranges(&para.unvisited)
+        )?;
+    }
+    writeln!(html, "</table>")?;
+    writeln!(html, "</body>")?;
+    writeln!(html, "</html>")?;
+    Ok(html)
+}
+
+pub fn write_report(
+    mut writer: impl Write,
+    title: &str,
+    report: &CoverageReport,
+    format: CoverageFormat,
+) -> Result<()> {
+    match format {
+        CoverageFormat::Json => {
+            let paras = report
+                .paras
+                .iter()
+                .map(|para| {
+                    serde_json::json!({
+                        "base_para": para.base_para,
+                        "para": para.para,
+                        "total": para.total,
+                        "visited": para.visited(),
+                        "percent": para.percent(),
+                        "unvisited": para.unvisited,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let data = serde_json::json!({
+                "title": title,
+                "total": report.total(),
+                "visited": report.visited(),
+                "percent": report.percent(),
+                "paras": paras,
+            });
+            serde_json::to_writer_pretty(&mut writer, &data)?;
+            writeln!(writer)?;
+        }
+        CoverageFormat::Html => writer.write_all(render_html(title, report)?.as_bytes())?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn ctx(para: &str, act: usize) -> RawContext {
+        RawContext {
+            cur_base_para: "init".to_string(),
+            cur_para: para.to_string(),
+            cur_act: act,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(unvisited_ranges(&[]), "");
+        assert_eq!(unvisited_ranges(&[0, 1, 2, 4, 6, 7]), "0-2, 4, 6-7");
+    }
+
+    #[test]
+    fn merge_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("coverage.json");
+        let mut first = Coverage::default();
+        first.update(&ctx("init", 0));
+        merge_coverage_file(&path, &first).unwrap();
+        assert_eq!(read_coverage(&path).unwrap(), first);
+
+        let mut second = Coverage::default();
+        second.update(&ctx("walk", 1));
+        merge_coverage_file(&path, &second).unwrap();
+        let merged = read_coverage(&path).unwrap();
+        assert!(merged.visited(&ctx("init", 0)));
+        assert!(merged.visited(&ctx("walk", 1)));
+        assert!(!merged.visited(&ctx("walk", 0)));
+    }
+
+    #[test]
+    fn html_escape() {
+        let report = CoverageReport {
+            paras: vec![ParagraphCoverage {
+                base_para: "init".to_string(),
+                para: "<a&b>".to_string(),
+                total: 2,
+                unvisited: vec![1],
+            }],
+        };
+        let mut html = vec![];
+        write_report(&mut html, "\"Title\"", &report, CoverageFormat::Html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("<title>Coverage of &quot;Title&quot;</title>"));
+        assert!(html.contains("<tr class=\"partial\"><td>init</td><td>&lt;a&amp;b&gt;</td>"));
+    }
+}
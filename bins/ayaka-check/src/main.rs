@@ -1,22 +1,88 @@
+mod coverage;
+mod transcript;
+mod watch;
+
 use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
-use ayaka_runtime::{anyhow::Result, *};
-use clap::Parser;
+use ayaka_runtime::{
+    anyhow::{anyhow, bail, Result},
+    *,
+};
+use clap::{Args, Parser, Subcommand};
+use coverage::*;
 use flexi_logger::{LogSpecification, Logger};
 use std::{
     ffi::OsString,
-    io::{stdin, stdout, Write},
+    fs::File,
+    io::{stdin, stdout, BufWriter, Write},
+    path::PathBuf,
     pin::pin,
 };
+use transcript::*;
+use watch::*;
 
 #[derive(Debug, Parser)]
-#[clap(about, version, author)]
+#[clap(
+    about,
+    version,
+    author,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 pub struct Options {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(required = true)]
     input: Vec<OsString>,
     #[clap(long)]
     auto: bool,
     #[clap(short, long)]
     locale: Option<Locale>,
+    #[clap(long)]
+    explore: bool,
+    #[clap(long, default_value_t = 64)]
+    depth: usize,
+    #[clap(long, value_delimiter = ',', conflicts_with = "choices_file")]
+    choices: Vec<usize>,
+    #[clap(long)]
+    choices_file: Option<PathBuf>,
+    #[clap(long)]
+    transcript: Option<PathBuf>,
+    #[clap(long)]
+    expect: Option<PathBuf>,
+    #[clap(long, conflicts_with_all = ["explore", "transcript", "expect"])]
+    watch: bool,
+    #[clap(long)]
+    lazy: bool,
+    #[clap(long)]
+    strict: bool,
+    #[clap(long)]
+    seed: Option<u64>,
+    #[clap(long, conflicts_with = "explore")]
+    coverage: Option<PathBuf>,
+    #[clap(long)]
+    pipeline: bool,
+    #[clap(long)]
+    fuel: Option<u64>,
+    #[clap(long)]
+    memory: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Report the line coverage merged from coverage files.
+    Coverage(CoverageOptions),
+}
+
+#[derive(Debug, Args)]
+struct CoverageOptions {
+    #[clap(required = true)]
+    input: Vec<OsString>,
+    #[clap(long, required = true)]
+    data: Vec<PathBuf>,
+    #[clap(long, value_enum)]
+    format: Option<CoverageFormat>,
+    #[clap(short, long, requires = "format")]
+    output: Option<PathBuf>,
 }
 
 fn read_line() -> Result<String> {
@@ -35,21 +101,102 @@ fn pause(auto: bool) -> Result<()> {
     Ok(())
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-    let opts = Options::parse();
-    let spec = LogSpecification::parse("warn,ayaka=debug")?;
-    let _log_handle = Logger::with(spec)
-        .log_to_stdout()
-        .set_palette("b1;3;2;4;6".to_string())
-        .use_utc()
-        .start()?;
-    let linker = WasmiLinker::new(())?;
-    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&opts.input)?
-        .open();
+fn explore(ctx: &mut Context<WasmiModule>, depth: usize) -> Result<()> {
+    let report = ctx.explore(ExploreOptions {
+        max_depth: depth,
+        ..Default::default()
+    });
+    for key in &report.unreachable {
+        println!("Unreachable paragraph: {key}");
+    }
+    for missing in &report.missing_next {
+        match &missing.from {
+            Some(from) => println!("Missing paragraph \"{}\" from {}", missing.target, from),
+            None => println!("Missing start paragraph \"{}\"", missing.target),
+        }
+    }
+    for line in &report.dead_switches {
+        println!("All switches disabled: {}:{}", line.para, line.act);
+    }
+    for line in &report.infinite_loops {
+        println!("Infinite loop: {}:{}", line.para, line.act);
+    }
+    println!(
+        "Endings: {}, truncated paths: {}",
+        report.endings, report.truncated
+    );
+    if report.aborted {
+        println!("Exploration aborted: too many steps.");
+    }
+    if !report.is_ok() {
+        bail!("Problems found in the game.");
+    }
+    Ok(())
+}
+
+fn print_pipeline(ctx: &Context<WasmiModule>) {
+    let runtime = ctx.runtime();
+    for (kind, pipeline) in [
+        ("Action", runtime.action_pipeline()),
+        ("Game", runtime.game_pipeline()),
+    ] {
+        println!("{kind} pipeline:");
+        for (i, name) in pipeline.iter().enumerate() {
+            match runtime.manifest(name) {
+                Some(manifest) => println!("{}. {} {}", i + 1, name, manifest.version),
+                None => println!("{}. {}", i + 1, name),
+            }
+        }
+    }
+}
+
+/// The diagnostics are logged when found,
+/// and they fail the check in strict mode.
+fn check_diagnostics(ctx: &Context<WasmiModule>, strict: bool, count: &mut usize) -> Result<()> {
+    let diagnostics = ctx.take_diagnostics();
+    if strict {
+        if let Some(diag) = diagnostics.into_iter().next() {
+            bail!(diag);
+        }
+    } else {
+        *count += diagnostics.len();
+    }
+    Ok(())
+}
+
+async fn reload(
+    ctx: &mut Context<WasmiModule>,
+    changed: &[PathBuf],
+    config: LinkerConfig,
+) -> Result<()> {
+    if changed
+        .iter()
+        .any(|p| p.extension().unwrap_or_default() == "wasm")
+    {
+        println!("Reloading plugins...");
+        ctx.reload_plugins(WasmiLinker::new(config)?).await?;
+    }
+    println!("Reloading paragraphs...");
+    match ctx.reload() {
+        Ok(true) => {}
+        Ok(false) => println!("Current paragraph removed, restart the game."),
+        // Keep the old game when the files are being edited.
+        Err(e) => println!("Cannot reload: {e}"),
+    }
+    Ok(())
+}
+
+async fn open(
+    builder: ContextBuilder<WasmiModule>,
+    input: &[OsString],
+    progress: bool,
+) -> Result<Context<WasmiModule>> {
+    let context = builder.with_paths(input)?.open();
     let mut context = pin!(context);
     while let Some(status) = context.next().await {
+        if !progress {
+            continue;
+        }
         match status {
             OpenStatus::LoadProfile => println!("Loading profile..."),
             OpenStatus::CreateRuntime => println!("Creating runtime..."),
@@ -61,44 +208,219 @@ async fn main() -> Result<()> {
             OpenStatus::LoadParagraph => println!("Loading paragraphs..."),
         }
     }
-    let mut ctx = context.await?;
+    context.await
+}
+
+async fn coverage(opts: CoverageOptions) -> Result<()> {
+    let linker = WasmiLinker::new(LinkerConfig::default())?;
+    let builder = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker).lazy(true);
+    // Keep the output clean for JSON and HTML.
+    let ctx = open(builder, &opts.input, opts.format.is_none()).await?;
+    let mut coverage = Coverage::default();
+    for path in &opts.data {
+        coverage.merge(&read_coverage(path)?);
+    }
+    let report = coverage.report(ctx.game());
+    let title = &ctx.game().config.title;
+    match (opts.format, &opts.output) {
+        (None, _) => print_report(&report),
+        (Some(format), None) => write_report(stdout().lock(), title, &report, format)?,
+        (Some(format), Some(path)) => {
+            write_report(BufWriter::new(File::create(path)?), title, &report, format)?
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let opts = Options::parse();
+    let spec = LogSpecification::parse("warn,ayaka=debug")?;
+    let _log_handle = Logger::with(spec)
+        .log_to_stdout()
+        .set_palette("b1;3;2;4;6".to_string())
+        .use_utc()
+        .start()?;
+    if let Some(Command::Coverage(opts)) = opts.command {
+        return coverage(opts).await;
+    }
+    let linker_config = LinkerConfig {
+        fuel: opts.fuel,
+        memory: opts.memory,
+        ..Default::default()
+    };
+    let linker = WasmiLinker::new(linker_config.clone())?;
+    let mut builder = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
+        .lazy(opts.lazy)
+        .strict(opts.strict);
+    if let Some(seed) = opts.seed {
+        builder = builder.seed(seed);
+    }
+    let mut ctx = open(builder, &opts.input, true).await?;
+    if opts.pipeline {
+        print_pipeline(&ctx);
+        return Ok(());
+    }
+    if opts.explore {
+        return explore(&mut ctx, opts.depth);
+    }
     ctx.set_start_context();
     let loc = opts.locale.unwrap_or_else(Locale::current);
-    while let Some(raw_ctx) = ctx.next_run() {
-        let action = ctx.get_action(&loc, &raw_ctx)?;
-        match action {
-            Action::Empty | Action::Custom(_) => {}
-            Action::Text(action) => {
-                if let Some(name) = &action.character {
-                    print!("_{name}_");
-                }
-                for s in &action.text {
-                    print!("{}", s.as_str());
+    let scripted = !opts.choices.is_empty() || opts.choices_file.is_some();
+    let mut watcher = if opts.watch {
+        Some(Watcher::new(&opts.input)?)
+    } else {
+        None
+    };
+    let mut writer = opts
+        .transcript
+        .as_ref()
+        .map(TranscriptWriter::create)
+        .transpose()?;
+    let mut entries = vec![];
+    let mut diagnostics = 0;
+    loop {
+        let mut choices = if let Some(path) = &opts.choices_file {
+            read_choices(path)?
+        } else {
+            opts.choices.clone()
+        }
+        .into_iter();
+        loop {
+            if let Some(watcher) = &mut watcher {
+                let changed = watcher.poll_interval()?;
+                if !changed.is_empty() {
+                    reload(&mut ctx, &changed, linker_config.clone()).await?;
                 }
-                pause(opts.auto)?;
             }
-            Action::Switches(switches) => {
-                for (i, s) in switches.iter().enumerate() {
-                    if s.enabled {
-                        print!("\n-{}- {}", i + 1, s.text);
-                    } else {
-                        print!("\n-x- {}", s.text);
+            let raw_ctx = ctx.next_run();
+            check_diagnostics(&ctx, opts.strict, &mut diagnostics)?;
+            let Some(raw_ctx) = raw_ctx else {
+                break;
+            };
+            let (action, source) = ctx.get_action_with_source(&loc, &raw_ctx)?;
+            check_diagnostics(&ctx, opts.strict, &mut diagnostics)?;
+            let mut choice = None;
+            let mut timed_out = false;
+            match &action {
+                Action::Empty | Action::Custom(_) => {}
+                Action::Text(action) => {
+                    if let Some(name) = &action.character {
+                        print!("_{name}_");
                     }
+                    for s in &action.text {
+                        print!("{}", s.as_str());
+                    }
+                    // Show the locale if the line falls back.
+                    if let Some(source) = source.filter(|source| source.level > 0) {
+                        print!(" [{}]", source.locale);
+                    }
+                    pause(opts.auto || scripted)?;
                 }
-                println!();
-                loop {
-                    let s = read_line()?;
-                    if let Ok(i) = s.trim().parse::<usize>() {
-                        let valid = i > 0 && i <= switches.len() && switches[i - 1].enabled;
-                        if valid {
-                            ctx.switch(i - 1);
-                            break;
+                Action::Switches(switches) => {
+                    for (i, s) in switches.iter().enumerate() {
+                        if s.hidden {
+                            continue;
+                        }
+                        if s.enabled {
+                            print!("\n-{}- {}", i + 1, s.text);
+                        } else {
+                            print!("\n-x- {}", s.text);
+                        }
+                    }
+                    println!();
+                    let timeout = switches.iter().find_map(|s| s.timeout);
+                    if let Some(timeout) = timeout {
+                        println!("(Timeout: {timeout}ms)");
+                    }
+                    let valid = |i: usize| {
+                        i > 0
+                            && i <= switches.len()
+                            && switches[i - 1].enabled
+                            && !switches[i - 1].hidden
+                    };
+                    if scripted {
+                        let i = choices.next().ok_or_else(|| {
+                            anyhow!(
+                                "No more choices at {}/{}:{}",
+                                raw_ctx.cur_base_para,
+                                raw_ctx.cur_para,
+                                raw_ctx.cur_act
+                            )
+                        })?;
+                        // Choice 0 lets the timer expire.
+                        if i == 0 && timeout.is_some() {
+                            let i = ctx
+                                .switch_timeout()?
+                                .ok_or_else(|| anyhow!("No default switch"))?;
+                            println!("> timeout");
+                            choice = Some(i + 1);
+                            timed_out = true;
+                        } else {
+                            if !valid(i) {
+                                bail!("Invalid choice {i}");
+                            }
+                            println!("> {i}");
+                            ctx.switch(i - 1)?;
+                            choice = Some(i);
+                        }
+                    } else {
+                        let default = if opts.auto && timeout.is_some() {
+                            ctx.switch_timeout()?
+                        } else {
+                            None
+                        };
+                        if let Some(i) = default {
+                            println!("> timeout");
+                            choice = Some(i + 1);
+                            timed_out = true;
+                        } else {
+                            loop {
+                                let s = read_line()?;
+                                if let Ok(i) = s.trim().parse::<usize>() {
+                                    if valid(i) {
+                                        ctx.switch(i - 1)?;
+                                        choice = Some(i);
+                                        break;
+                                    }
+                                }
+                                println!("Invalid switch, enter again!");
+                            }
                         }
                     }
-                    println!("Invalid switch, enter again!");
                 }
             }
+            let mut entry = TranscriptEntry::new(&raw_ctx, action);
+            entry.choice = choice;
+            entry.timeout = timed_out;
+            if let Some(writer) = &mut writer {
+                writer.write(&entry)?;
+            }
+            if opts.expect.is_some() {
+                entries.push(entry);
+            }
+        }
+        if let Some(path) = &opts.coverage {
+            merge_coverage_file(path, ctx.coverage())?;
         }
+        let Some(watcher) = &mut watcher else {
+            break;
+        };
+        println!("Game ended, waiting for changes...");
+        let changed = watcher.wait()?;
+        reload(&mut ctx, &changed, linker_config.clone()).await?;
+        ctx.set_start_context();
+    }
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
+    if diagnostics > 0 {
+        println!("Diagnostics found: {diagnostics}");
+    }
+    if let Some(path) = &opts.expect {
+        let expected = read_transcript(path)?;
+        diff_transcript(&expected, &entries)?;
+        println!("Transcript matches.");
     }
     Ok(())
 }
@@ -0,0 +1,180 @@
+use ayaka_runtime::{
+    anyhow::{anyhow, bail, Result},
+    *,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// One line of the transcript.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub base_para: String,
+    pub para: String,
+    pub act: usize,
+    pub action: Action,
+    /// The chosen switch, start by 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub choice: Option<usize>,
+    /// Whether the switch is chosen by timeout.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timeout: bool,
+}
+
+impl TranscriptEntry {
+    pub fn new(ctx: &RawContext, action: Action) -> Self {
+        Self {
+            base_para: ctx.cur_base_para.clone(),
+            para: ctx.cur_para.clone(),
+            act: ctx.cur_act,
+            action,
+            choice: None,
+            timeout: false,
+        }
+    }
+}
+
+/// Read the choices from a file.
+/// The choices are separated by commas or whitespaces.
+pub fn read_choices(path: impl AsRef<Path>) -> Result<Vec<usize>> {
+    let content = std::fs::read_to_string(path)?;
+    content
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| Ok(s.parse::<usize>()?))
+        .collect()
+}
+
+pub struct TranscriptWriter {
+    writer: BufWriter<File>,
+}
+
+impl TranscriptWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, entry: &TranscriptEntry) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, entry)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub fn read_transcript(path: impl AsRef<Path>) -> Result<Vec<TranscriptEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("Invalid transcript at line {}: {}", i + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Compare the transcript with the golden one,
+/// and report the first difference.
+pub fn diff_transcript(expected: &[TranscriptEntry], actual: &[TranscriptEntry]) -> Result<()> {
+    for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        if expected != actual {
+            bail!(
+                "Transcript differs at line {}:\nexpected: {}\n  actual: {}",
+                i + 1,
+                serde_json::to_string(expected)?,
+                serde_json::to_string(actual)?
+            );
+        }
+    }
+    if expected.len() != actual.len() {
+        bail!(
+            "Transcript length differs: expected {}, actual {}",
+            expected.len(),
+            actual.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(act: usize, text: &str) -> TranscriptEntry {
+        let ctx = RawContext {
+            cur_base_para: "init".to_string(),
+            cur_para: "init".to_string(),
+            cur_act: act,
+            ..Default::default()
+        };
+        let mut action = ActionText::default();
+        action.push_back_chars(text);
+        TranscriptEntry::new(&ctx, Action::Text(action))
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transcript.jsonl");
+        let mut entries = vec![entry(0, "hello"), entry(1, "world")];
+        entries[1].choice = Some(2);
+        entries[1].timeout = true;
+
+        let mut writer = TranscriptWriter::create(&path).unwrap();
+        for entry in &entries {
+            writer.write(entry).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let read = read_transcript(&path).unwrap();
+        assert_eq!(read, entries);
+        diff_transcript(&entries, &read).unwrap();
+    }
+
+    #[test]
+    fn invalid_line() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transcript.jsonl");
+        std::fs::write(&path, "\n{}\n").unwrap();
+        let err = read_transcript(&path).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid transcript at line 2"));
+    }
+
+    #[test]
+    fn mismatch() {
+        let expected = vec![entry(0, "hello"), entry(1, "world")];
+        let actual = vec![entry(0, "hello"), entry(1, "word")];
+        let err = diff_transcript(&expected, &actual).unwrap_err();
+        assert!(err.to_string().starts_with("Transcript differs at line 2"));
+
+        let err = diff_transcript(&expected, &expected[..1]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Transcript length differs: expected 2, actual 1"
+        );
+    }
+
+    #[test]
+    fn choices() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("choices.txt");
+        std::fs::write(&path, "1, 2\n3 ").unwrap();
+        assert_eq!(read_choices(&path).unwrap(), [1, 2, 3]);
+        std::fs::write(&path, "1,a").unwrap();
+        assert!(read_choices(&path).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use ayaka_runtime::anyhow::Result;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// The min interval between two scans of the files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A simple watcher polling the modified time of files.
+pub struct Watcher {
+    roots: Vec<PathBuf>,
+    stamps: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    /// Watch the directory of a YAML config, or the package files.
+    pub fn new(inputs: &[OsString]) -> Result<Self> {
+        let roots = inputs
+            .iter()
+            .map(|input| {
+                let path = Path::new(input);
+                if path.extension().unwrap_or_default() == "yaml" {
+                    path.parent().unwrap_or(Path::new(".")).to_path_buf()
+                } else {
+                    path.to_path_buf()
+                }
+            })
+            .collect::<Vec<_>>();
+        let stamps = Self::scan(&roots)?;
+        Ok(Self {
+            roots,
+            stamps,
+            last_poll: Instant::now(),
+        })
+    }
+
+    fn scan(roots: &[PathBuf]) -> Result<HashMap<PathBuf, SystemTime>> {
+        let mut stamps = HashMap::new();
+        let mut stack = roots.to_vec();
+        while let Some(path) = stack.pop() {
+            // The files may be removed after listed, e.g., the temp files of editors.
+            let metadata = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if metadata.is_dir() {
+                let entries = match std::fs::read_dir(&path) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                for entry in entries {
+                    stack.push(entry?.path());
+                }
+            } else {
+                stamps.insert(path, metadata.modified()?);
+            }
+        }
+        Ok(stamps)
+    }
+
+    /// Get the changed files since last poll.
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>> {
+        self.last_poll = Instant::now();
+        let stamps = Self::scan(&self.roots)?;
+        let mut changed = stamps
+            .iter()
+            .filter(|(path, time)| self.stamps.get(*path) != Some(*time))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        changed.extend(
+            self.stamps
+                .keys()
+                .filter(|path| !stamps.contains_key(*path))
+                .cloned(),
+        );
+        self.stamps = stamps;
+        Ok(changed)
+    }
+
+    /// Get the changed files since last poll,
+    /// but only scan the files if [`POLL_INTERVAL`] has passed since then.
+    pub fn poll_interval(&mut self) -> Result<Vec<PathBuf>> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            Ok(vec![])
+        } else {
+            self.poll()
+        }
+    }
+
+    /// Wait until some files changed.
+    pub fn wait(&mut self) -> Result<Vec<PathBuf>> {
+        loop {
+            let changed = self.poll()?;
+            if !changed.is_empty() {
+                return Ok(changed);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs::File, time::Duration};
+    use tempfile::tempdir;
+
+    #[test]
+    fn changes() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("config.yaml"), "").unwrap();
+        std::fs::create_dir(root.join("paras")).unwrap();
+        let para = root.join("paras").join("init.yaml");
+        std::fs::write(&para, "").unwrap();
+
+        let mut watcher = Watcher::new(&[root.join("config.yaml").into()]).unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+
+        // The modified time may not change in a short time.
+        let file = File::options().write(true).open(&para).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(watcher.poll().unwrap(), std::slice::from_ref(&para));
+
+        let new_para = root.join("paras").join("new.yaml");
+        std::fs::write(&new_para, "").unwrap();
+        assert_eq!(watcher.poll().unwrap(), [new_para]);
+
+        std::fs::remove_file(&para).unwrap();
+        assert_eq!(watcher.poll().unwrap(), [para]);
+    }
+
+    #[test]
+    fn interval() {
+        let dir = tempdir().unwrap();
+        let mut watcher = Watcher::new(&[dir.path().into()]).unwrap();
+        std::fs::write(dir.path().join("init.yaml"), "").unwrap();
+        // Polled just now.
+        assert!(watcher.poll_interval().unwrap().is_empty());
+        std::thread::sleep(POLL_INTERVAL);
+        assert_eq!(watcher.poll_interval().unwrap().len(), 1);
+    }
+}
@@ -157,7 +157,7 @@ async fn open_game(handle: AppHandle, storage: State<'_, Storage>) -> CommandRes
 
     const OPEN_STATUS_EVENT: &str = "ayaka://open_status";
     let mut model = storage.model.write().await;
-    let linker = WasmiLinker::new(())?;
+    let linker = WasmiLinker::new(LinkerConfig::default())?;
     let builder = ContextBuilder::<WasmiModule>::new(FrontendType::Html, linker);
     let builder = if storage.config.is_empty() {
         let files = show_pick_files(&handle, &window).await?;
@@ -319,10 +319,20 @@ async fn current_title(storage: State<'_, Storage>) -> CommandResult<Option<Stri
 
 #[command]
 async fn switch(i: usize, storage: State<'_, Storage>) -> CommandResult<()> {
-    storage.model.write().await.switch(i);
+    storage.model.write().await.switch(i)?;
     Ok(())
 }
 
+#[command]
+async fn switch_timeout(storage: State<'_, Storage>) -> CommandResult<Option<usize>> {
+    Ok(storage.model.write().await.switch_timeout()?)
+}
+
+#[command]
+async fn take_diagnostics(storage: State<'_, Storage>) -> CommandResult<Vec<Diagnostic>> {
+    Ok(storage.model.read().await.context().take_diagnostics())
+}
+
 #[command]
 async fn history(storage: State<'_, Storage>) -> CommandResult<Vec<(Action, Option<Action>)>> {
     Ok(storage.model.read().await.current_history().rev().collect())
@@ -448,6 +458,8 @@ pub fn run() -> tauri::Result<()> {
             current_title,
             current_visited,
             switch,
+            switch_timeout,
+            take_diagnostics,
             history,
         ])
         .run({
@@ -60,6 +60,17 @@ impl SettingsManager for FileSettingsManager {
         Ok(self.records_path_root(game).join("global.json"))
     }
 
+    fn coverage_path(&self, game: &str) -> Result<PathBuf> {
+        Ok(self
+            .local_data_dir
+            .join("coverage")
+            .join(format!("{game}.json")))
+    }
+
+    fn plugin_data_path(&self, game: &str) -> Result<PathBuf> {
+        Ok(self.local_data_dir.join("data").join(game))
+    }
+
     fn records_path(&self, game: &str) -> Result<impl Iterator<Item = Result<PathBuf>>> {
         let ctx_path = self.records_path_root(game);
         Ok(std::fs::read_dir(ctx_path)?.filter_map(|entry| {
@@ -96,6 +96,8 @@ export enum ActionLineType {
 export interface Switch {
     text: string,
     enabled: boolean,
+    hidden: boolean,
+    timeout?: number,
 }
 
 export function ayaka_version(): Promise<string> {
@@ -197,6 +199,23 @@ export function switch_(i: number): Promise<void> {
     return invoke("switch", { i: i })
 }
 
+export function switch_timeout(): Promise<number | undefined> {
+    return invoke("switch_timeout")
+}
+
+export interface Diagnostic {
+    kind: "InvalidArgCount" | "MissingResource" | "MissingVariable" | "UnknownCommand" | "InvalidParagraphFile" | "MissingParagraph" | "InvalidCondition" | "PluginFailure",
+    base_para: string,
+    para: string,
+    act: number,
+    locale?: string,
+    message: string,
+}
+
+export function take_diagnostics(): Promise<Diagnostic[]> {
+    return invoke("take_diagnostics")
+}
+
 export function history(): Promise<[Action, Action | undefined][]> {
     return invoke("history")
 }
@@ -31,7 +31,7 @@ async fn main() -> Result<()> {
         .set_palette("b1;3;2;4;6".to_string())
         .use_utc()
         .start()?;
-    let linker = WasmiLinker::new(())?;
+    let linker = WasmiLinker::new(LinkerConfig::default())?;
     let context = ContextBuilder::<WasmiModule>::new(FrontendType::Latex, linker)
         .with_paths(&opts.input)?
         .open();
@@ -111,7 +111,7 @@ async fn main() -> Result<()> {
                     Action::Switches(switches) => {
                         output
                             .environment("itemize", |output| async move {
-                                for s in switches.iter() {
+                                for s in switches.iter().filter(|s| !s.hidden) {
                                     output.command0("item").await?;
                                     if s.enabled {
                                         output.write(&s.text).await?;
@@ -13,6 +13,11 @@ use std::{
     sync::{LazyLock, Mutex},
 };
 
+#[export]
+fn plugin_manifest() -> PluginManifest {
+    PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
 #[export]
 fn plugin_type() -> PluginType {
     PluginType::builder().line(["exec"]).build()
@@ -24,7 +29,9 @@ extern "C" {
 }
 
 static RUNTIME: LazyLock<Runtime> = LazyLock::new(Runtime::new);
-static PROGRAM_CACHE: LazyLock<Mutex<HashMap<LineKey, Program>>> =
+// The source is kept with the program,
+// because a line may run both its condition and an `exec` program.
+static PROGRAM_CACHE: LazyLock<Mutex<HashMap<LineKey, (String, Program)>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
@@ -41,10 +48,13 @@ fn exec(mut ctx: LineProcessContext) -> LineProcessResult {
         cur_para: ctx.ctx.cur_para,
         cur_act: ctx.ctx.cur_act,
     };
+    let source = ctx.props["exec"].get_str();
     let mut cache = PROGRAM_CACHE.lock().unwrap();
-    let exec = cache
-        .entry(key)
-        .or_insert_with(|| __parse(&ctx.props["exec"].get_str()));
+    if !cache.get(&key).is_some_and(|(cached, _)| *cached == source) {
+        let program = __parse(&source);
+        cache.insert(key.clone(), (source.into_owned(), program));
+    }
+    let (_, exec) = &cache[&key];
     let mut table = VarTable::new(&RUNTIME, &mut ctx.ctx.locals);
     table.call(exec);
     let vars = table.vars;
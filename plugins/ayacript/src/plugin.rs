@@ -34,14 +34,18 @@ impl Linker<HostModule> for HostLinker {
         unimplemented!()
     }
 
-    fn create(&self, _binary: &[u8]) -> Result<HostModule> {
+    fn create(&self, _name: &str, _binary: &[u8]) -> Result<HostModule> {
         unimplemented!()
     }
 
+    fn set_limits(&mut self, _limits: ModuleLimits) {}
+
     fn import(&mut self, _ns: impl Into<String>, _funcs: HashMap<String, ()>) -> Result<()> {
         Ok(())
     }
 
+    fn clear_imports(&mut self) {}
+
     fn wrap_raw(&self, _f: impl (Fn(Self, i32, i32) -> Result<Vec<u8>>) + Send + Sync + 'static) {}
 }
 
@@ -56,11 +60,16 @@ impl<'a> LinkerHandle<'a, HostModule> for HostLinker {
         unimplemented!()
     }
 
-    fn slice<T>(&self, _start: i32, _len: i32, _f: impl FnOnce(&[u8]) -> T) -> T {
+    fn slice<T>(&self, _start: i32, _len: i32, _f: impl FnOnce(&[u8]) -> T) -> Result<T> {
         unimplemented!()
     }
 
-    fn slice_mut<T>(&mut self, _start: i32, _len: i32, _f: impl FnOnce(&mut [u8]) -> T) -> T {
+    fn slice_mut<T>(
+        &mut self,
+        _start: i32,
+        _len: i32,
+        _f: impl FnOnce(&mut [u8]) -> T,
+    ) -> Result<T> {
         unimplemented!()
     }
 }
@@ -2,6 +2,11 @@
 
 use ayaka_bindings::*;
 
+#[export]
+fn plugin_manifest() -> PluginManifest {
+    PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
 #[export]
 fn plugin_type() -> PluginType {
     PluginType::default()
@@ -2,6 +2,11 @@
 
 use ayaka_bindings::*;
 
+#[export]
+fn plugin_manifest() -> PluginManifest {
+    PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
 #[export]
 fn plugin_type() -> PluginType {
     PluginType::builder()
@@ -9,9 +14,24 @@ fn plugin_type() -> PluginType {
         .build()
 }
 
+/// Check the count of the args.
+/// A command with wrong args is logged, and outputs nothing.
+fn check_args(cmd: &str, args: &[String], count: usize) -> bool {
+    let matches = args.len() == count;
+    if !matches {
+        log::error!(
+            "Command `{cmd}` requires {count} args, but got {}",
+            args.len()
+        );
+    }
+    matches
+}
+
 #[export]
 fn par(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
-    assert!(args.is_empty());
+    if !check_args("par", &args, 0) {
+        return TextProcessResult::default();
+    }
     let mut res = TextProcessResult::default();
     match ctx.frontend {
         FrontendType::Text => res.text.push_back_chars("\n"),
@@ -27,7 +47,9 @@ fn text_font(
     ctx: TextProcessContext,
     fonts: &str,
 ) -> TextProcessResult {
-    assert_eq!(args.len(), 1);
+    if !check_args(cmd, &args, 1) {
+        return TextProcessResult::default();
+    }
     let mut res = TextProcessResult::default();
     match ctx.frontend {
         FrontendType::Text => res.text.push_back_chars(&args[0]),
@@ -62,7 +84,9 @@ fn texttt(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
 
 #[export]
 fn ruby(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
-    assert_eq!(args.len(), 2);
+    if !check_args("ruby", &args, 2) {
+        return TextProcessResult::default();
+    }
     let mut res = TextProcessResult::default();
     match ctx.frontend {
         FrontendType::Text => res
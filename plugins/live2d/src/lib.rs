@@ -1,16 +1,21 @@
 #![deny(unsafe_code)]
 
 use ayaka_bindings::{fs::HostFS, vfs::*, *};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+
+#[export]
+fn plugin_manifest() -> PluginManifest {
+    PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+}
 
 #[export]
 fn plugin_type() -> PluginType {
     PluginType::builder().line(["show", "hide"]).game().build()
 }
 
-fn find_model(ch: &str, game_props: &HashMap<String, String>) -> Option<VfsPath> {
+fn find_model(ch: &str, ch_models: Option<&str>) -> Option<VfsPath> {
     let root: VfsPath = HostFS.into();
-    game_props.get("ch_models").and_then(|ch_models| {
+    ch_models.and_then(|ch_models| {
         let base_dir = root.join(ch_models).ok()?;
         ["model.json", "model3.json"]
             .iter()
@@ -70,9 +75,12 @@ fn hide(ctx: LineProcessContext) -> LineProcessResult {
 
 #[export]
 fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
-    if let Some(names) = ctx.props.remove("ch_names") {
+    let ch_models = setting(&ctx.config, &ctx.props, "ch_models");
+    let names = setting(&ctx.config, &ctx.props, "ch_names");
+    ctx.props.remove("ch_names");
+    if let Some(names) = names {
         for name in names.split(',') {
-            if let Some(path) = find_model(name, &ctx.props) {
+            if let Some(path) = find_model(name, ch_models.as_deref()) {
                 ctx.props
                     .insert(format!("ch_{name}_model"), path.as_str().to_string());
             }
@@ -8,9 +8,15 @@ use std::{
     collections::{HashMap, VecDeque},
 };
 
+#[export]
+fn plugin_manifest() -> PluginManifest {
+    PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
 #[export]
 fn plugin_type() -> PluginType {
-    PluginType::builder().action().build()
+    // Render the text after other action plugins.
+    PluginType::builder().action().priority(10).build()
 }
 
 #[export]
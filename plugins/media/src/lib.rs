@@ -2,6 +2,11 @@
 
 use ayaka_bindings::{fs::HostFS, vfs::*, *};
 
+#[export]
+fn plugin_manifest() -> PluginManifest {
+    PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
 #[export]
 fn plugin_type() -> PluginType {
     PluginType::builder()
@@ -49,10 +54,7 @@ fn file_ctx(
     temp: bool,
 ) -> LineProcessResult {
     let root: VfsPath = HostFS.into();
-    let base_dir = ctx
-        .game_props
-        .get(game_prop)
-        .and_then(|p| root.join(p).ok());
+    let base_dir = setting(&ctx.config, &ctx.game_props, game_prop).and_then(|p| root.join(p).ok());
     file(
         &ctx.props[prop].get_str(),
         base_dir.as_ref(),
@@ -83,8 +85,7 @@ fn process_action(mut ctx: ActionProcessContext) -> ActionProcessResult {
     let voice_id = ctx.ctx.cur_act.to_string();
     let res = file(
         &voice_id,
-        ctx.game_props
-            .get("voices")
+        setting(&ctx.config, &ctx.game_props, "voices")
             .and_then(|p| root.join(p).ok()?.join(&ctx.ctx.cur_para).ok())
             .as_ref(),
         "voice",
@@ -98,7 +99,7 @@ fn process_action(mut ctx: ActionProcessContext) -> ActionProcessResult {
 #[export]
 fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
     let root: VfsPath = HostFS.into();
-    let base_dir = ctx.props.get("bgs").and_then(|p| root.join(p).ok());
+    let base_dir = setting(&ctx.config, &ctx.props, "bgs").and_then(|p| root.join(p).ok());
     if let Some(bg) = ctx.props.get_mut("bg") {
         if let Some(path) = find_exists(bg, base_dir.as_ref(), &["png", "jpg", "gif"]) {
             *bg = path.as_str().to_string();
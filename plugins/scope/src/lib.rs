@@ -0,0 +1,80 @@
+#![deny(unsafe_code)]
+
+use ayaka_bindings::*;
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+#[export]
+fn plugin_manifest() -> PluginManifest {
+    PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+#[export]
+fn plugin_type() -> PluginType {
+    PluginType::builder().paragraph().state().build()
+}
+
+const DEFAULT_PREFIX: &str = "p_";
+
+// The times each paragraph is entered.
+static VISITS: LazyLock<Mutex<HashMap<String, i64>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[export]
+fn on_paragraph_enter(ctx: ParagraphProcessContext) -> ParagraphProcessResult {
+    log::debug!("Enter paragraph \"{}\" from \"{}\"", ctx.to, ctx.from);
+    *VISITS.lock().unwrap().entry(ctx.to).or_default() += 1;
+    ParagraphProcessResult {
+        locals: ctx.ctx.locals,
+    }
+}
+
+#[export]
+fn on_paragraph_exit(mut ctx: ParagraphProcessContext) -> ParagraphProcessResult {
+    log::debug!("Exit paragraph \"{}\" to \"{}\"", ctx.from, ctx.to);
+    let prefix = ctx
+        .game_props
+        .get("scope_prefix")
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_PREFIX);
+    ctx.ctx.locals.retain(|key, _| !key.starts_with(prefix));
+    ParagraphProcessResult {
+        locals: ctx.ctx.locals,
+    }
+}
+
+#[export]
+fn visits(args: Vec<RawValue>) -> RawValue {
+    let tag = args.first().map(|tag| tag.get_str()).unwrap_or_default();
+    let count = VISITS
+        .lock()
+        .unwrap()
+        .get(tag.as_ref())
+        .copied()
+        .unwrap_or_default();
+    RawValue::Num(count)
+}
+
+// The state is saved as lines of tags and counts.
+#[export]
+fn save_state() -> Vec<u8> {
+    let mut data = String::new();
+    for (tag, count) in VISITS.lock().unwrap().iter() {
+        data.push_str(&format!("{tag}\t{count}\n"));
+    }
+    data.into_bytes()
+}
+
+#[export]
+fn load_state(data: Vec<u8>) {
+    let data = String::from_utf8_lossy(&data);
+    let visits = data
+        .lines()
+        .filter_map(|line| {
+            let (tag, count) = line.split_once('\t')?;
+            Some((tag.to_string(), count.parse().ok()?))
+        })
+        .collect();
+    *VISITS.lock().unwrap() = visits;
+}
@@ -15,7 +15,7 @@ pub fn export(_attr: TokenStream, input: TokenStream) -> TokenStream {
         #[doc(hidden)]
         #[export_name = #name_str]
         extern "C" fn #expname(len: usize, data: *const u8) -> u64 {
-            ::ayaka_bindings::__export(len, data, #name)
+            ::ayaka_bindings::__export(#name_str, len, data, #name)
         }
         #input
     };
@@ -62,6 +62,43 @@ pub struct RawContext {
     pub cur_act: usize,
     /// Current local variables.
     pub locals: VarMap,
+    /// The call stack of subroutine paragraphs.
+    /// The last frame is the innermost one.
+    #[serde(default)]
+    pub call_stack: Vec<CallFrame>,
+    /// The state of the random number generator.
+    #[serde(default)]
+    pub rng: RngState,
+    /// The paragraph file of [`Self::entered_para`].
+    #[serde(default)]
+    pub entered_base_para: String,
+    /// The paragraph notified to the paragraph plugins.
+    /// It is empty if no paragraph has been entered.
+    #[serde(default)]
+    pub entered_para: String,
+}
+
+/// The state of the random number generator.
+///
+/// The `count`-th number is generated from an RNG seeded with `seed` and `count`,
+/// so that the numbers could be reproduced from a record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct RngState {
+    /// The seed of the game.
+    pub seed: u64,
+    /// The count of generated numbers.
+    pub count: u64,
+}
+
+/// The return position of a subroutine call.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct CallFrame {
+    /// The base paragraph tag of the caller.
+    pub cur_base_para: String,
+    /// The paragraph tag of the caller.
+    pub cur_para: String,
+    /// The text index to return to.
+    pub cur_act: usize,
 }
 
 /// The `text` is a [`VecDeque<ActionSubText>`].
@@ -150,10 +187,20 @@ pub enum Action {
 }
 
 /// One switch in the switches of an [`Action`].
+///
+/// The missing fields are deserialized as default,
+/// e.g., a switch is not hidden unless specified.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, FallbackSpec)]
+#[serde(default)]
 pub struct Switch {
     /// The switch text.
     pub text: String,
     /// Whether the switch is enabled.
     pub enabled: bool,
+    /// Whether the switch is hidden.
+    /// A hidden switch should not be shown by frontends.
+    pub hidden: bool,
+    /// The timeout in milliseconds, only set for the default switch of timed switches.
+    /// The frontend should choose this switch after the timeout.
+    pub timeout: Option<u64>,
 }
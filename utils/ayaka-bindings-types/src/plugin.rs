@@ -2,6 +2,58 @@ use crate::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// The version of the ABI between the runtime and plugins.
+///
+/// It should be increased when the types passed to plugins,
+/// or the imported functions, change incompatibly.
+pub const ABI_VERSION: u32 = 2;
+
+/// The manifest of a plugin.
+///
+/// Every plugin should provide a function `plugin_manifest`,
+/// which returns [`PluginManifest`].
+///
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export]
+/// fn plugin_manifest() -> PluginManifest {
+///     PluginManifest::new(env!("CARGO_CRATE_NAME"), env!("CARGO_PKG_VERSION"))
+///         .dependency("random")
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    /// The name of the plugin, which is the file name without extension.
+    pub name: String,
+    /// The version of the plugin.
+    pub version: String,
+    /// The required [`ABI_VERSION`].
+    pub abi_version: u32,
+    /// The names of plugins this plugin depends on.
+    /// They are loaded before this plugin.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+impl PluginManifest {
+    /// Creates a manifest with current [`ABI_VERSION`].
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            abi_version: ABI_VERSION,
+            dependencies: vec![],
+        }
+    }
+
+    /// Add a dependency.
+    pub fn dependency(mut self, name: impl Into<String>) -> Self {
+        self.dependencies.push(name.into());
+        self
+    }
+}
+
 /// The bit flags to describe plugin type.
 ///
 /// Every plugin should provide a function `plugin_type`,
@@ -29,6 +81,19 @@ pub struct PluginType {
     /// The game plugin.
     /// This plugin processes the game properties after it is loaded.
     pub game: bool,
+    /// The paragraph plugin.
+    /// This plugin is notified when entering or exiting paragraphs.
+    #[serde(default)]
+    pub paragraph: bool,
+    /// The state plugin.
+    /// This plugin saves its own state into the records,
+    /// by `save_state` and `load_state`.
+    #[serde(default)]
+    pub state: bool,
+    /// The priority in the action and game pipelines.
+    /// The plugins with smaller priority run earlier.
+    #[serde(default)]
+    pub priority: i32,
 }
 
 impl PluginType {
@@ -70,6 +135,24 @@ impl PluginTypeBuilder {
         self
     }
 
+    /// A paragraph plugin.
+    pub fn paragraph(mut self) -> Self {
+        self.data.paragraph = true;
+        self
+    }
+
+    /// A state plugin.
+    pub fn state(mut self) -> Self {
+        self.data.state = true;
+        self
+    }
+
+    /// The priority in the action and game pipelines.
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.data.priority = priority;
+        self
+    }
+
     /// Build a [`PluginType`].
     pub fn build(self) -> PluginType {
         self.data
@@ -109,6 +192,9 @@ pub struct ActionProcessContext {
     pub ctx: RawContext,
     /// The current action.
     pub action: ActionText,
+    /// The config of the plugin in the game profile.
+    #[serde(default)]
+    pub config: VarMap,
 }
 
 #[derive(Debug, Serialize)]
@@ -118,6 +204,7 @@ pub struct ActionProcessContextRef<'a> {
     pub frontend: FrontendType,
     pub ctx: &'a RawContext,
     pub action: &'a ActionText,
+    pub config: &'a VarMap,
 }
 
 /// The result of action plugins.
@@ -151,6 +238,9 @@ pub struct TextProcessContext {
     pub game_props: HashMap<String, String>,
     /// The frontend type.
     pub frontend: FrontendType,
+    /// The config of the plugin in the game profile.
+    #[serde(default)]
+    pub config: VarMap,
 }
 
 #[derive(Debug, Serialize)]
@@ -158,6 +248,7 @@ pub struct TextProcessContext {
 pub struct TextProcessContextRef<'a> {
     pub game_props: &'a HashMap<String, String>,
     pub frontend: FrontendType,
+    pub config: &'a VarMap,
 }
 
 /// The result of commands in text plugins.
@@ -188,6 +279,9 @@ pub struct GameProcessContext {
     pub author: String,
     /// The global properties of the game.
     pub props: HashMap<String, String>,
+    /// The config of the plugin in the game profile.
+    #[serde(default)]
+    pub config: VarMap,
 }
 
 #[derive(Debug, Serialize)]
@@ -196,6 +290,7 @@ pub struct GameProcessContextRef<'a> {
     pub title: &'a str,
     pub author: &'a str,
     pub props: &'a HashMap<String, String>,
+    pub config: &'a VarMap,
 }
 
 /// The result of game plugins.
@@ -233,6 +328,9 @@ pub struct LineProcessContext {
     pub ctx: RawContext,
     /// The full properties of the custom command.
     pub props: VarMap,
+    /// The config of the plugin in the game profile.
+    #[serde(default)]
+    pub config: VarMap,
 }
 
 #[derive(Debug, Serialize)]
@@ -242,6 +340,7 @@ pub struct LineProcessContextRef<'a> {
     pub frontend: FrontendType,
     pub ctx: &'a RawContext,
     pub props: &'a VarMap,
+    pub config: &'a VarMap,
 }
 
 /// The result of commands in line plugins.
@@ -253,3 +352,71 @@ pub struct LineProcessResult {
     /// The temp variables.
     pub vars: VarMap,
 }
+
+/// The argument to paragraph plugin.
+///
+/// Every paragraph plugin should implement `on_paragraph_enter` and `on_paragraph_exit`:
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export]
+/// fn plugin_type() -> PluginType {
+///     PluginType::builder().paragraph().build()
+/// }
+///
+/// #[export]
+/// fn on_paragraph_enter(ctx: ParagraphProcessContext) -> ParagraphProcessResult {
+///     ParagraphProcessResult { locals: ctx.ctx.locals }
+/// }
+///
+/// #[export]
+/// fn on_paragraph_exit(ctx: ParagraphProcessContext) -> ParagraphProcessResult {
+///     ParagraphProcessResult { locals: ctx.ctx.locals }
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParagraphProcessContext {
+    /// The global properties of the game profile.
+    pub game_props: HashMap<String, String>,
+    /// The frontend type.
+    pub frontend: FrontendType,
+    /// The current context.
+    pub ctx: RawContext,
+    /// The paragraph file of the old paragraph.
+    #[serde(default)]
+    pub from_base_para: String,
+    /// The tag of the old paragraph.
+    /// It is empty at the start of the game.
+    pub from: String,
+    /// The paragraph file of the new paragraph.
+    #[serde(default)]
+    pub to_base_para: String,
+    /// The tag of the new paragraph.
+    /// It is empty at the end of the game.
+    pub to: String,
+    /// The config of the plugin in the game profile.
+    #[serde(default)]
+    pub config: VarMap,
+}
+
+#[derive(Debug, Serialize)]
+#[doc(hidden)]
+pub struct ParagraphProcessContextRef<'a> {
+    pub game_props: &'a HashMap<String, String>,
+    pub frontend: FrontendType,
+    pub ctx: &'a RawContext,
+    pub from_base_para: &'a str,
+    pub from: &'a str,
+    pub to_base_para: &'a str,
+    pub to: &'a str,
+    pub config: &'a VarMap,
+}
+
+/// The result of paragraph plugins.
+/// See examples at [`ParagraphProcessContext`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ParagraphProcessResult {
+    /// The new variables.
+    /// They replace the current ones.
+    pub locals: VarMap,
+}
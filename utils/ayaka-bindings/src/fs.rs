@@ -1,8 +1,13 @@
 use crate::import;
 use ayaka_bindings_types::{FileMetadata, FileSeekFrom};
-use std::io::{Read, Result, Seek, SeekFrom};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
 use vfs::{error::VfsErrorKind, *};
 
+/// The path where the writable data directory of the plugin is mounted.
+///
+/// The other paths are read-only.
+pub const DATA_PATH: &str = "/.data";
+
 #[import("fs")]
 extern "C" {
     fn __read_dir(path: &str) -> Option<Vec<String>>;
@@ -14,6 +19,38 @@ extern "C" {
 
     fn __file_read(fd: u64, ptr: i32, len: i32) -> Option<usize>;
     fn __file_seek(fd: u64, pos: FileSeekFrom) -> Option<u64>;
+
+    fn __data_read_dir(path: &str) -> Option<Vec<String>>;
+    fn __data_metadata(path: &str) -> Option<FileMetadata>;
+    fn __data_exists(path: &str) -> bool;
+    fn __data_create_dir(path: &str) -> bool;
+    fn __data_remove_file(path: &str) -> bool;
+    fn __data_remove_dir(path: &str) -> bool;
+
+    fn __data_open_file(path: &str) -> Option<u64>;
+    fn __data_create_file(path: &str) -> Option<u64>;
+    fn __data_append_file(path: &str) -> Option<u64>;
+    fn __data_close_file(fd: u64);
+
+    fn __data_file_read(fd: u64, ptr: i32, len: i32) -> Option<usize>;
+    fn __data_file_write(fd: u64, ptr: i32, len: i32) -> Option<usize>;
+    fn __data_file_flush(fd: u64) -> bool;
+    fn __data_file_seek(fd: u64, pos: FileSeekFrom) -> Option<u64>;
+}
+
+/// Get the path relative to the data directory,
+/// if the path is under [`DATA_PATH`].
+fn data_path(path: &str) -> Option<&str> {
+    path.strip_prefix(DATA_PATH)
+        .filter(|p| p.is_empty() || p.starts_with('/'))
+}
+
+fn data_result(res: bool) -> VfsResult<()> {
+    if res {
+        Ok(())
+    } else {
+        Err(VfsErrorKind::FileNotFound.into())
+    }
 }
 
 #[derive(Debug, Default)]
@@ -21,48 +58,91 @@ pub struct HostFS;
 
 impl FileSystem for HostFS {
     fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
-        match __read_dir(path) {
+        let paths = match data_path(path) {
+            Some(path) => __data_read_dir(path).map(|paths| {
+                paths
+                    .into_iter()
+                    .map(|p| format!("{DATA_PATH}{p}"))
+                    .collect()
+            }),
+            None => __read_dir(path),
+        };
+        match paths {
             Some(paths) => Ok(Box::new(paths.into_iter())),
             None => Err(VfsErrorKind::FileNotFound.into()),
         }
     }
 
-    fn create_dir(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsErrorKind::NotSupported.into())
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        match data_path(path) {
+            Some(path) => data_result(__data_create_dir(path)),
+            None => Err(VfsErrorKind::NotSupported.into()),
+        }
     }
 
     fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
-        match __open_file(path) {
-            Some(fd) => Ok(Box::new(HostFile { fd })),
-            None => Err(VfsErrorKind::FileNotFound.into()),
+        match data_path(path) {
+            Some(path) => match __data_open_file(path) {
+                Some(fd) => Ok(Box::new(DataFile { fd })),
+                None => Err(VfsErrorKind::FileNotFound.into()),
+            },
+            None => match __open_file(path) {
+                Some(fd) => Ok(Box::new(HostFile { fd })),
+                None => Err(VfsErrorKind::FileNotFound.into()),
+            },
         }
     }
 
-    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
-        Err(VfsErrorKind::NotSupported.into())
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        match data_path(path) {
+            Some(path) => match __data_create_file(path) {
+                Some(fd) => Ok(Box::new(DataFile { fd })),
+                None => Err(VfsErrorKind::FileNotFound.into()),
+            },
+            None => Err(VfsErrorKind::NotSupported.into()),
+        }
     }
 
-    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
-        Err(VfsErrorKind::NotSupported.into())
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        match data_path(path) {
+            Some(path) => match __data_append_file(path) {
+                Some(fd) => Ok(Box::new(DataFile { fd })),
+                None => Err(VfsErrorKind::FileNotFound.into()),
+            },
+            None => Err(VfsErrorKind::NotSupported.into()),
+        }
     }
 
     fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
-        match __metadata(path) {
+        let meta = match data_path(path) {
+            Some(path) => __data_metadata(path),
+            None => __metadata(path),
+        };
+        match meta {
             Some(meta) => Ok(meta.into()),
             None => Err(VfsErrorKind::FileNotFound.into()),
         }
     }
 
     fn exists(&self, path: &str) -> VfsResult<bool> {
-        Ok(__exists(path))
+        match data_path(path) {
+            Some(path) => Ok(__data_exists(path)),
+            None => Ok(__exists(path)),
+        }
     }
 
-    fn remove_file(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsErrorKind::NotSupported.into())
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        match data_path(path) {
+            Some(path) => data_result(__data_remove_file(path)),
+            None => Err(VfsErrorKind::NotSupported.into()),
+        }
     }
 
-    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsErrorKind::NotSupported.into())
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        match data_path(path) {
+            Some(path) => data_result(__data_remove_dir(path)),
+            None => Err(VfsErrorKind::NotSupported.into()),
+        }
     }
 }
 
@@ -89,3 +169,43 @@ impl Drop for HostFile {
         __close_file(self.fd)
     }
 }
+
+/// A file in the data directory.
+#[derive(Debug)]
+struct DataFile {
+    fd: u64,
+}
+
+impl Read for DataFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        __data_file_read(self.fd, buf.as_mut_ptr() as _, buf.len() as _)
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+}
+
+impl Write for DataFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        __data_file_write(self.fd, buf.as_ptr() as _, buf.len() as _)
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if __data_file_flush(self.fd) {
+            Ok(())
+        } else {
+            Err(std::io::ErrorKind::NotFound.into())
+        }
+    }
+}
+
+impl Seek for DataFile {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        __data_file_seek(self.fd, pos.into()).ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+}
+
+impl Drop for DataFile {
+    fn drop(&mut self) {
+        __data_close_file(self.fd)
+    }
+}
@@ -17,6 +17,7 @@ mod logger;
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
     alloc::{self, Layout},
+    collections::HashMap,
     marker::Tuple,
 };
 
@@ -51,18 +52,26 @@ unsafe fn __abi_alloc_from(data: &[u8]) -> (*mut u8, usize) {
     (slice.as_mut_ptr(), slice.len())
 }
 
+/// Calls the exported function with the args from the host.
+///
+/// If the args or the result cannot be (de)serialized,
+/// it panics with the name of the export.
+/// The panic message is logged to the host before the instance traps.
 #[doc(hidden)]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub fn __export<Params: DeserializeOwned + Tuple, Res: Serialize>(
+    name: &str,
     len: usize,
     data: *const u8,
     f: impl FnOnce<Params, Output = Res>,
 ) -> u64 {
     logger::PluginLogger::init();
     let data = unsafe { std::slice::from_raw_parts(data, len) };
-    let data = rmp_serde::from_slice(data).expect("cannot deserialize input args");
+    let data = rmp_serde::from_slice(data)
+        .unwrap_or_else(|e| panic!("cannot deserialize the args of `{name}`: {e}"));
     let res = f.call_once(data);
-    let data = rmp_serde::to_vec(&res).expect("cannot serialize result");
+    let data = rmp_serde::to_vec(&res)
+        .unwrap_or_else(|e| panic!("cannot serialize the result of `{name}`: {e}"));
     let (ptr, len) = unsafe { __abi_alloc_from(&data) };
     ((len as u64) << 32) | (ptr as u64)
 }
@@ -81,4 +90,13 @@ pub fn __import<Params: Serialize, Res: DeserializeOwned>(
     data.expect("cannot deseriaize result")
 }
 
+/// Get the setting from the plugin config,
+/// or from the game props for compatibility.
+pub fn setting(config: &VarMap, props: &HashMap<String, String>, key: &str) -> Option<String> {
+    config
+        .get(key)
+        .map(|value| value.get_str().into_owned())
+        .or_else(|| props.get(key).cloned())
+}
+
 pub use ayaka_bindings_impl::{export, import};
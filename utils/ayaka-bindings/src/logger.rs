@@ -17,6 +17,9 @@ impl PluginLogger {
         INIT.call_once(|| {
             log::set_logger(&PluginLogger).expect("cannot set logger");
             log::set_max_level(log::LevelFilter::Trace);
+            // The message is lost when the instance traps,
+            // so log it to the host first.
+            std::panic::set_hook(Box::new(|info| log::error!("{info}")));
         });
     }
 }
@@ -67,6 +67,9 @@ impl GlobalRecord {
 pub struct ActionRecord {
     /// The history actions.
     pub history: Vec<RawContext>,
+    /// The states of state plugins, keyed by the plugin names.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub plugin_states: HashMap<String, Vec<u8>>,
 }
 
 impl ActionRecord {
@@ -122,6 +125,24 @@ pub trait SettingsManager {
         self.save_file(self.global_record_path(game)?, data, false)
     }
 
+    /// Get the coverage path.
+    fn coverage_path(&self, game: &str) -> Result<PathBuf>;
+
+    /// Load [`Coverage`].
+    fn load_coverage(&self, game: &str) -> Result<Coverage> {
+        self.load_file(self.coverage_path(game)?)
+    }
+
+    /// Save [`Coverage`].
+    fn save_coverage(&self, game: &str, data: &Coverage) -> Result<()> {
+        self.save_file(self.coverage_path(game)?, data, false)
+    }
+
+    /// Get the writable data directory of the plugins.
+    ///
+    /// Every plugin owns a sub directory named after itself.
+    fn plugin_data_path(&self, game: &str) -> Result<PathBuf>;
+
     /// Get an iterator of record paths.
     fn records_path(&self, game: &str) -> Result<impl Iterator<Item = Result<PathBuf>>>;
 
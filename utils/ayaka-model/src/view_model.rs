@@ -46,7 +46,7 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
 
     /// Open the game with context.
     #[stream(OpenGameStatus, lifetime = 'a)]
-    pub async fn open_game<'a>(&'a mut self, context: Context<M>) -> Result<()> {
+    pub async fn open_game<'a>(&'a mut self, mut context: Context<M>) -> Result<()> {
         yield OpenGameStatus::LoadSettings;
         let settings = unwrap_or_default_log!(
             self.settings_manager.load_settings(),
@@ -61,6 +61,23 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
             "Load global records failed"
         );
         self.global_record = Some(global_record);
+        let coverage = unwrap_or_default_log!(
+            self.settings_manager
+                .load_coverage(&context.game().config.title),
+            "Load coverage failed"
+        );
+        context.merge_coverage(&coverage);
+        let data_dir = self
+            .settings_manager
+            .plugin_data_path(&context.game().config.title)
+            .and_then(|path| {
+                std::fs::create_dir_all(&path)?;
+                Ok(Some(vfs::VfsPath::from(vfs::PhysicalFS::new(path))))
+            });
+        context.set_data_dir(unwrap_or_default_log!(
+            data_dir,
+            "Open plugin data directory failed"
+        ));
 
         yield OpenGameStatus::LoadRecords;
         self.records = unwrap_or_default_log!(
@@ -125,9 +142,14 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
             .expect("should be called after open_game")
     }
 
+    /// The executed lines, merged with the ones of previous sessions.
+    pub fn coverage(&self) -> &Coverage {
+        self.context().coverage()
+    }
+
     /// Get the avaliable locales from paragraphs.
     pub fn avaliable_locale(&self) -> impl Iterator<Item = &Locale> {
-        self.context().game().paras.keys()
+        self.context().game().locales()
     }
 
     /// Start a new game.
@@ -136,11 +158,13 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
     /// to ensure there's content in the game, and switch to the
     /// first line of the first paragraph.
     pub fn init_new(&mut self) {
-        let ctx = self.context().game().start_context();
+        let ctx = self.context().start_context();
         self.current_record = ActionRecord::default();
         // This is the start.
         self.current_raw_context = None;
         self.context_mut().set_context(ctx);
+        // Reset the states of the last game.
+        self.context().set_plugin_states(&Default::default());
     }
 
     /// Start a game with record.
@@ -153,6 +177,8 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
         // `ctx` points to the next raw context.
         ctx.cur_act += 1;
         self.context_mut().set_context(ctx);
+        self.context()
+            .set_plugin_states(&self.current_record.plugin_states);
     }
 
     /// Start a game with the index of records.
@@ -171,7 +197,7 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
             )
             .and_then(|p| p.texts.get(ctx.cur_act));
         let is_text = cur_text
-            .map(|line| matches!(line, Line::Text(_)))
+            .map(|line| matches!(line.inner(), Line::Text(_)))
             .unwrap_or_default();
         if is_text {
             self.current_record.history.push(ctx.clone());
@@ -257,14 +283,23 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
     }
 
     /// Choose a switch item by index.
-    pub fn switch(&mut self, i: usize) {
+    pub fn switch(&mut self, i: usize) -> Result<()> {
         log::debug!("Switch {i}");
-        self.context_mut().switch(i);
+        self.context_mut().switch(i)
+    }
+
+    /// Choose the default switch item when the timer of switches expires.
+    /// Returns the chosen index.
+    pub fn switch_timeout(&mut self) -> Result<Option<usize>> {
+        let i = self.context_mut().switch_timeout()?;
+        log::debug!("Switch timeout {i:?}");
+        Ok(i)
     }
 
     /// Save current [`ActionRecord`] to the records.
     pub fn save_current_to(&mut self, index: usize) {
-        let record = self.current_record.clone();
+        let mut record = self.current_record.clone();
+        record.plugin_states = self.context().plugin_states();
         if index >= self.records.len() {
             self.records.push(record);
         } else {
@@ -278,6 +313,8 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
         self.settings_manager.save_settings(self.settings())?;
         self.settings_manager
             .save_global_record(game, self.global_record())?;
+        self.settings_manager
+            .save_coverage(game, self.context().coverage())?;
         self.settings_manager.save_records(game, self.records())?;
         Ok(())
     }
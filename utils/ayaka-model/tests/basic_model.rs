@@ -57,6 +57,18 @@ impl SettingsManager for NopSettingsManager {
         Ok(self.records_path_root(game).join("global.json"))
     }
 
+    fn coverage_path(&self, game: &str) -> Result<PathBuf> {
+        Ok(self
+            .dir
+            .path()
+            .join("coverage")
+            .join(format!("{game}.json")))
+    }
+
+    fn plugin_data_path(&self, game: &str) -> Result<PathBuf> {
+        Ok(self.dir.path().join("data").join(game))
+    }
+
     fn records_path(&self, game: &str) -> Result<impl Iterator<Item = Result<PathBuf>>> {
         let ctx_path = self.records_path_root(game);
         Ok(std::fs::read_dir(ctx_path)?.filter_map(|entry| {
@@ -88,7 +100,7 @@ const CONFIG_PATH: &str = "tests/basic/config.yaml";
 async fn progress() {
     let mut model =
         GameViewModel::<NopSettingsManager, WasmiModule>::new(NopSettingsManager::new().unwrap());
-    let linker = WasmiLinker::new(()).unwrap();
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
     let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
         .with_paths(&[CONFIG_PATH])
         .unwrap()
@@ -130,7 +142,7 @@ async fn paras() {
         manager
     };
     let mut model = GameViewModel::<NopSettingsManager, WasmiModule>::new(manager);
-    let linker = WasmiLinker::new(()).unwrap();
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
     let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
         .with_paths(&[CONFIG_PATH])
         .unwrap()
@@ -157,3 +169,50 @@ async fn paras() {
         ]
     )
 }
+
+#[tokio::test(flavor = "current_thread")]
+async fn coverage() {
+    let manager = NopSettingsManager::new().unwrap();
+    let mut last = Coverage::default();
+    last.update(&RawContext {
+        cur_base_para: "init".to_string(),
+        cur_para: "init".to_string(),
+        cur_act: 3,
+        ..Default::default()
+    });
+    manager.save_coverage("Basic", &last).unwrap();
+
+    let mut model = GameViewModel::<NopSettingsManager, WasmiModule>::new(manager);
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
+    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
+        .with_paths(&[CONFIG_PATH])
+        .unwrap()
+        .open()
+        .await
+        .unwrap();
+    model.open_game(context).await.unwrap();
+    model.init_new();
+    assert!(model.next_run());
+    assert!(model.next_run());
+    let report = model.coverage().report(model.context().game());
+    assert_eq!(report.paras.len(), 1);
+    assert_eq!(report.paras[0].unvisited, vec![2]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn data_dir() {
+    let manager = NopSettingsManager::new().unwrap();
+    let path = manager.plugin_data_path("Basic").unwrap();
+    let mut model = GameViewModel::<NopSettingsManager, WasmiModule>::new(manager);
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
+    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
+        .with_paths(&[CONFIG_PATH])
+        .unwrap()
+        .open()
+        .await
+        .unwrap();
+    assert!(context.runtime().data_dir().is_none());
+    model.open_game(context).await.unwrap();
+    assert!(model.context().runtime().data_dir().is_some());
+    assert!(path.is_dir());
+}
@@ -6,7 +6,7 @@ const CONFIG_PATH: &str = "tests/basic/config.yaml";
 
 #[tokio::test(flavor = "current_thread")]
 async fn progress() {
-    let linker = WasmiLinker::new(()).unwrap();
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
     let mut context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
         .with_paths(&[CONFIG_PATH])
         .unwrap()
@@ -29,7 +29,7 @@ async fn progress() {
 
 #[tokio::test(flavor = "current_thread")]
 async fn config() {
-    let linker = WasmiLinker::new(()).unwrap();
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
     let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
         .with_paths(&[CONFIG_PATH])
         .unwrap()
@@ -62,7 +62,7 @@ fn paras(mut context: Context<WasmiModule>, loc: Locale, expected_actions: &[Act
 
 #[tokio::test(flavor = "current_thread")]
 async fn paras_en() {
-    let linker = WasmiLinker::new(()).unwrap();
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
     let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
         .with_paths(&[CONFIG_PATH])
         .unwrap()
@@ -84,7 +84,7 @@ async fn paras_en() {
 
 #[tokio::test(flavor = "current_thread")]
 async fn paras_zh() {
-    let linker = WasmiLinker::new(()).unwrap();
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
     let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
         .with_paths(&[CONFIG_PATH])
         .unwrap()
@@ -0,0 +1,76 @@
+//! The Wasmtime backend compiles the real plugins here,
+//! so they should be built first by `make plugins` in the repository root,
+//! as `make test` does.
+
+use ayaka_model::*;
+use ayaka_plugin_wasmtime::{WasmtimeLinker, WasmtimeModule};
+use std::path::Path;
+use tempfile::tempdir;
+
+const CONFIG_PATH: &str = "tests/random/config.yaml";
+
+async fn open(cache_dir: &Path) -> Context<WasmtimeModule> {
+    let linker = WasmtimeLinker::new(LinkerConfig {
+        cache_dir: Some(cache_dir.to_path_buf()),
+        ..Default::default()
+    })
+    .unwrap();
+    ContextBuilder::<WasmtimeModule>::new(FrontendType::Text, linker)
+        .seed(42)
+        .with_paths(&[CONFIG_PATH])
+        .unwrap()
+        .open()
+        .await
+        .expect("the plugins should be built by `make plugins`")
+}
+
+fn run(context: &mut Context<WasmtimeModule>) -> Vec<String> {
+    let loc = locale!("en");
+    context.set_start_context();
+    let mut texts = vec![];
+    while let Some(raw_ctx) = context.next_run() {
+        if let Action::Text(text) = context.get_action(&loc, &raw_ctx).unwrap() {
+            texts.push(text.to_string());
+        }
+    }
+    texts
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn cache() {
+    let dir = tempdir().unwrap();
+    let texts = run(&mut open(dir.path()).await);
+    // One for each plugin.
+    let cached = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .unwrap_or_default()
+                == "cwasm"
+        })
+        .count();
+    assert_eq!(cached, 2);
+
+    // Loaded from the cache.
+    assert_eq!(run(&mut open(dir.path()).await), texts);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn cache_corrupted() {
+    let dir = tempdir().unwrap();
+    let texts = run(&mut open(dir.path()).await);
+    for entry in std::fs::read_dir(dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().unwrap_or_default() == "cwasm" {
+            std::fs::write(&path, b"corrupted").unwrap();
+        }
+    }
+
+    // The corrupted files are not loaded, and compiled again.
+    assert_eq!(run(&mut open(dir.path()).await), texts);
+    assert_eq!(run(&mut open(dir.path()).await), texts);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/call/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn call_return() {
+    let mut context = open(CONFIG_PATH).await;
+    context.set_start_context();
+    let loc = locale!("en");
+    let actions = actions(&mut context, &loc);
+    assert_eq!(
+        actions,
+        [
+            text_chars("0"),
+            Action::Empty,
+            text_chars("1"),
+            text_chars("2"),
+            text_chars("3"),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn call_record() {
+    let mut context = open(CONFIG_PATH).await;
+    context.set_start_context();
+    // 0, call, 1
+    let raw_ctx = (0..3).filter_map(|_| context.next_run()).last().unwrap();
+    assert_eq!(raw_ctx.cur_para, "sub");
+    assert_eq!(
+        raw_ctx.call_stack,
+        [CallFrame {
+            cur_base_para: "init".to_string(),
+            cur_para: "init".to_string(),
+            cur_act: 2,
+        }]
+    );
+
+    // Resume from a saved record, like `GameViewModel::init_context`.
+    let record = serde_json::to_string(&raw_ctx).unwrap();
+    let mut ctx: RawContext = serde_json::from_str(&record).unwrap();
+    ctx.cur_act += 1;
+    context.set_context(ctx);
+    let loc = locale!("en");
+    let actions = actions(&mut context, &loc);
+    assert_eq!(actions, [text_chars("2"), text_chars("3")]);
+}
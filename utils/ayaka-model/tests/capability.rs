@@ -0,0 +1,25 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+
+#[tokio::test(flavor = "current_thread")]
+async fn restricted() {
+    let context = open("tests/capability/config.yaml").await;
+    let capabilities = context.game().config.plugins.module("media").capabilities;
+    assert_eq!(capabilities.dirs, Some(vec!["bg".to_string()]));
+    assert!(!capabilities.rand);
+    // Not specified.
+    assert_eq!(
+        context.game().config.plugins.module("random").capabilities,
+        PluginCapabilities::default()
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn denied() {
+    // The media plugin requires the fs imports.
+    assert!(open_with(builder(), "tests/capability/denied.yaml")
+        .await
+        .is_err());
+}
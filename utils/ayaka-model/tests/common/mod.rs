@@ -0,0 +1,47 @@
+//! Fixtures shared by the integration tests.
+//!
+//! Every test crate only uses a part of them.
+#![allow(dead_code)]
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
+use std::path::Path;
+
+/// Create a text frontend builder with the default wasmi linker.
+pub fn builder() -> ContextBuilder<WasmiModule> {
+    builder_for(FrontendType::Text)
+}
+
+/// Create a builder for `frontend` with the default wasmi linker.
+pub fn builder_for(frontend: FrontendType) -> ContextBuilder<WasmiModule> {
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
+    ContextBuilder::<WasmiModule>::new(frontend, linker)
+}
+
+/// Open the game config at `path` with `builder`.
+pub async fn open_with(
+    builder: ContextBuilder<WasmiModule>,
+    path: impl AsRef<Path>,
+) -> anyhow::Result<Context<WasmiModule>> {
+    builder.with_paths(&[path])?.open().await
+}
+
+/// Open the game config at `path` with the default options.
+pub async fn open(path: impl AsRef<Path>) -> Context<WasmiModule> {
+    open_with(builder(), path).await.unwrap()
+}
+
+/// Run the context until the end, and collect the actions in `loc`.
+pub fn actions(context: &mut Context<WasmiModule>, loc: &Locale) -> Vec<Action> {
+    std::iter::from_fn(|| {
+        let raw_ctx = context.next_run();
+        raw_ctx.map(|raw_ctx| context.get_action(loc, &raw_ctx).unwrap())
+    })
+    .collect()
+}
+
+pub fn text_chars(s: impl Into<String>) -> Action {
+    let mut text = ActionText::default();
+    text.push_back_chars(s.into());
+    Action::Text(text)
+}
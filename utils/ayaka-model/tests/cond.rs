@@ -0,0 +1,94 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+use common::*;
+use std::{collections::HashMap, path::Path};
+use tempfile::{tempdir, TempDir};
+
+const CONFIG_PATH: &str = "tests/cond/config.yaml";
+
+const NO_EXEC_CONFIG: &str = r#"
+title: Cond
+author: Berrysoft
+base_lang: en
+paras: paras
+start: init
+"#;
+
+async fn paras(loc: Locale, expected_actions: &[Action]) {
+    let mut context = open(CONFIG_PATH).await;
+    context.set_start_context();
+    assert_eq!(&actions(&mut context, &loc), expected_actions);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn cond_en() {
+    paras(
+        locale!("en"),
+        &[
+            Action::Custom(HashMap::default()),
+            text_chars("1"),
+            text_chars("3"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn cond_zh() {
+    paras(
+        locale!("zh"),
+        &[
+            Action::Custom(HashMap::default()),
+            text_chars("一"),
+            text_chars("三"),
+        ],
+    )
+    .await;
+}
+
+fn no_exec_game() -> TempDir {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(root.join("config.yaml"), NO_EXEC_CONFIG).unwrap();
+    let paras = root.join("paras").join("en");
+    std::fs::create_dir_all(&paras).unwrap();
+    std::fs::write(
+        paras.join("init.yaml"),
+        r#"
+- tag: init
+  texts:
+    - text: 1
+      if: $a > 1
+    - 2
+"#,
+    )
+    .unwrap();
+    dir
+}
+
+async fn open_no_exec(root: &Path, lazy: bool) -> anyhow::Result<Context<WasmiModule>> {
+    open_with(builder().lazy(lazy), root.join("config.yaml")).await
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn no_exec() {
+    let dir = no_exec_game();
+    assert!(open_no_exec(dir.path(), false).await.is_err());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn no_exec_lazy() {
+    let dir = no_exec_game();
+    let mut context = open_no_exec(dir.path(), true).await.unwrap();
+    context.set_start_context();
+    let loc = locale!("en");
+    let actions = actions(&mut context, &loc);
+    // The condition is false, and it is reported.
+    assert_eq!(actions, [text_chars("2")]);
+    let diagnostics = context.take_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::InvalidCondition);
+    assert_eq!(diagnostics[0].act, 0);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/branch/config.yaml";
+
+fn play(context: &mut Context<WasmiModule>) {
+    context.set_start_context();
+    while let Some(raw_ctx) = context.next_run() {
+        if raw_ctx.cur_act == 1 && raw_ctx.cur_para == "init" {
+            context.switch(0).unwrap();
+        }
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn report() {
+    let mut context = open(CONFIG_PATH).await;
+    let report = context.coverage().report(context.game());
+    assert_eq!(report.total(), 4);
+    assert_eq!(report.visited(), 0);
+
+    play(&mut context);
+    // Exploring doesn't count.
+    context.explore(ExploreOptions::default());
+
+    let report = context.coverage().report(context.game());
+    assert_eq!(
+        report.paras,
+        vec![
+            ParagraphCoverage {
+                base_para: "init".to_string(),
+                para: "init".to_string(),
+                total: 2,
+                unvisited: vec![],
+            },
+            ParagraphCoverage {
+                base_para: "init".to_string(),
+                para: "orphan".to_string(),
+                total: 1,
+                unvisited: vec![0],
+            },
+            ParagraphCoverage {
+                base_para: "init".to_string(),
+                para: "walk".to_string(),
+                total: 1,
+                unvisited: vec![],
+            },
+        ]
+    );
+    assert_eq!(report.percent(), 75.0);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn merge() {
+    let mut first = open(CONFIG_PATH).await;
+    first.set_start_context();
+    first.next_run().unwrap();
+
+    let mut second = open(CONFIG_PATH).await;
+    second.merge_coverage(first.coverage());
+    let raw_ctx = RawContext {
+        cur_base_para: "init".to_string(),
+        cur_para: "init".to_string(),
+        cur_act: 0,
+        ..Default::default()
+    };
+    assert!(second.coverage().visited(&raw_ctx));
+
+    play(&mut second);
+    let report = second.coverage().report(second.game());
+    assert_eq!(report.visited(), 3);
+}
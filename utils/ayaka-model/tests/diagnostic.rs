@@ -0,0 +1,85 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/diagnostic/config.yaml";
+
+async fn open(strict: bool) -> Context<WasmiModule> {
+    let mut context = open_with(builder().strict(strict), CONFIG_PATH)
+        .await
+        .unwrap();
+    context.set_start_context();
+    context
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn diagnostics() {
+    let mut context = open(false).await;
+    let loc = locale!("en");
+    let mut diagnostics = vec![];
+    while let Some(raw_ctx) = context.next_run() {
+        context.get_action(&loc, &raw_ctx).unwrap();
+        diagnostics.extend(context.take_diagnostics());
+    }
+    diagnostics.extend(context.take_diagnostics());
+    let diagnostics = diagnostics
+        .into_iter()
+        .map(|diag| (diag.kind, diag.act, diag.locale))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diagnostics,
+        [
+            (DiagnosticKind::MissingVariable, 0, Some(loc.clone())),
+            (DiagnosticKind::MissingResource, 1, Some(loc.clone())),
+            (DiagnosticKind::UnknownCommand, 2, Some(loc.clone())),
+            // The problems when running lines have no locale.
+            (DiagnosticKind::UnknownCommand, 0, None),
+            (DiagnosticKind::MissingParagraph, 0, None),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn dedup() {
+    let mut context = open(false).await;
+    let loc = locale!("en");
+    let raw_ctx = context.next_run().unwrap();
+    // The same line is evaluated again, e.g., when the history is shown.
+    for _ in 0..2 {
+        context.get_action(&loc, &raw_ctx).unwrap();
+    }
+    assert_eq!(context.take_diagnostics().len(), 1);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn strict() {
+    let mut context = open(true).await;
+    let loc = locale!("en");
+    let raw_ctx = context.next_run().unwrap();
+    let err = context.get_action(&loc, &raw_ctx).unwrap_err();
+    let diag = err.downcast_ref::<Diagnostic>().unwrap();
+    assert_eq!(diag.kind, DiagnosticKind::MissingVariable);
+    assert_eq!(diag.para, "init");
+
+    // Skip the problematic lines.
+    for _ in 0..2 {
+        context.next_run().unwrap();
+    }
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        text_chars("ok")
+    );
+
+    // The run stops at the unknown line command.
+    context.take_diagnostics();
+    for _ in 0..2 {
+        assert!(context.next_run().is_none());
+        let diagnostics = context.take_diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::UnknownCommand);
+        assert_eq!(diagnostics[0].para, "run");
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/branch/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn explore() {
+    let mut context = open(CONFIG_PATH).await;
+    let report = context.explore(ExploreOptions::default());
+    assert!(!report.is_ok());
+    assert_eq!(report.unreachable, [ParagraphKey::new("init", "orphan")]);
+    assert_eq!(
+        report.missing_next,
+        [MissingNext {
+            from: Some(ParagraphKey::new("init", "walk")),
+            target: "missing".to_string(),
+        }]
+    );
+    assert!(report.dead_switches.is_empty());
+    assert!(report.infinite_loops.is_empty());
+    assert_eq!(report.endings, 0);
+    assert_eq!(report.truncated, 0);
+    assert!(!report.aborted);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn explore_depth() {
+    let mut context = open(CONFIG_PATH).await;
+    let report = context.explore(ExploreOptions {
+        max_depth: 0,
+        ..Default::default()
+    });
+    assert_eq!(report.truncated, 1);
+    assert!(report.missing_next.is_empty());
+    assert_eq!(
+        report.unreachable,
+        [
+            ParagraphKey::new("init", "orphan"),
+            ParagraphKey::new("init", "walk")
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn explore_restore() {
+    let mut context = open(CONFIG_PATH).await;
+    context.set_start_context();
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_act, 0);
+
+    // The missing paragraph found by exploring is not reported as a diagnostic,
+    // and the game continues from the current line.
+    context.explore(ExploreOptions::default());
+    assert!(context.take_diagnostics().is_empty());
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_para, "init");
+    assert_eq!(raw_ctx.cur_act, 1);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn explore_merge() {
+    let mut context = open("tests/merge/config.yaml").await;
+    // The paths only differing by `$?` merge.
+    let report = context.explore(ExploreOptions::default());
+    assert!(report.is_ok());
+    assert_eq!(report.endings, 1);
+}
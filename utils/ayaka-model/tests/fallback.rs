@@ -0,0 +1,38 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/fallback/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn fallback_chain() {
+    let mut context = open(CONFIG_PATH).await;
+    context.set_start_context();
+    let loc = locale!("zh-Hant");
+    assert_eq!(
+        context.game().locale_chain(&loc).collect::<Vec<_>>(),
+        [&locale!("zh-Hant"), &locale!("zh-Hans"), &locale!("ja")]
+    );
+    assert_eq!(
+        context.current_paragraph_title(&loc).map(|s| s.as_str()),
+        Some("开始")
+    );
+    let actions = std::iter::from_fn(|| {
+        let raw_ctx = context.next_run();
+        raw_ctx.map(|raw_ctx| {
+            let (action, source) = context.get_action_with_source(&loc, &raw_ctx).unwrap();
+            let source = source.unwrap();
+            (action, source.level, source.locale)
+        })
+    })
+    .collect::<Vec<_>>();
+    assert_eq!(
+        actions,
+        [
+            (text_chars("hant0"), 0, locale!("zh-Hant")),
+            (text_chars("hans1"), 1, locale!("zh-Hans")),
+            (text_chars("ja2"), 2, locale!("ja")),
+        ]
+    );
+}
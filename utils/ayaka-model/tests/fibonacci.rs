@@ -20,7 +20,7 @@ fn custom_default() -> Action {
 
 #[tokio::test(flavor = "current_thread")]
 async fn calculate() {
-    let linker = WasmiLinker::new(()).unwrap();
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
     let mut context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
         .with_paths(&[CONFIG_PATH])
         .unwrap()
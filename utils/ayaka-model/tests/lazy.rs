@@ -0,0 +1,90 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+use common::*;
+use std::path::Path;
+use tempfile::{tempdir, TempDir};
+
+const CONFIG: &str = r#"
+title: Lazy
+author: Berrysoft
+base_lang: en
+paras: paras
+start: init
+"#;
+
+fn write_para(root: &Path, loc: &str, name: &str, content: &str) {
+    let dir = root.join("paras").join(loc);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(format!("{name}.yaml")), content).unwrap();
+}
+
+fn game() -> TempDir {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(root.join("config.yaml"), CONFIG).unwrap();
+    write_para(
+        root,
+        "en",
+        "init",
+        r#"
+- tag: init
+  texts:
+    - 0
+  next: second
+"#,
+    );
+    write_para(
+        root,
+        "en",
+        "second",
+        r#"
+- tag: second
+  texts:
+    - 1
+"#,
+    );
+    // The broken files are never needed.
+    write_para(root, "en", "broken", "- tag: [");
+    write_para(root, "zh-Hans", "init", "- tag: [");
+    dir
+}
+
+async fn open(root: &Path, lazy: bool) -> anyhow::Result<Context<WasmiModule>> {
+    open_with(builder().lazy(lazy), root.join("config.yaml")).await
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn eager() {
+    let dir = game();
+    assert!(open(dir.path(), false).await.is_err());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn lazy() {
+    let dir = game();
+    let mut context = open(dir.path(), true).await.unwrap();
+    context.set_start_context();
+    let loc = locale!("en");
+    let actions = actions(&mut context, &loc);
+    assert_eq!(actions, [text_chars("0"), text_chars("1")]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn lazy_broken() {
+    let dir = game();
+    write_para(dir.path(), "en", "second", "- tag: [");
+    let mut context = open(dir.path(), true).await.unwrap();
+    context.set_start_context();
+    assert!(context.next_run().is_some());
+    assert!(context.next_run().is_none());
+    let diagnostics = context.take_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::InvalidParagraphFile);
+    // The error is kept, instead of an empty file.
+    assert!(context
+        .game()
+        .para_file_error(&locale!("en"), "second")
+        .is_some());
+}
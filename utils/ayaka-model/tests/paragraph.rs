@@ -0,0 +1,43 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/paragraph/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn scope() {
+    let mut context = open(CONFIG_PATH).await;
+    context.set_start_context();
+    let loc = locale!("en");
+
+    // The entered paragraph is kept in the record.
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_act, 0);
+    assert_eq!(raw_ctx.entered_para, "init");
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.locals.get("p_count"), Some(&RawValue::Num(1)));
+    assert_eq!(context.get_action(&loc, &raw_ctx).unwrap(), text_chars("1"));
+
+    // The scoped variable is removed when exiting the paragraph.
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_para, "second");
+    assert!(!raw_ctx.locals.contains_key("p_count"));
+    assert_eq!(raw_ctx.locals.get("total"), Some(&RawValue::Num(1)));
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        text_chars("Second")
+    );
+
+    // Going back to the entered paragraph exits and enters it again.
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.locals.get("v"), Some(&RawValue::Num(1)));
+    context.next_run().unwrap();
+    context.switch(0).unwrap();
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.locals.get("v"), Some(&RawValue::Num(2)));
+    context.next_run().unwrap();
+    context.switch(1).unwrap();
+
+    assert!(context.next_run().is_none());
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+use common::*;
+
+async fn open(path: &str) -> anyhow::Result<Context<WasmiModule>> {
+    open_with(builder_for(FrontendType::Html), path).await
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn priority() {
+    let context = open("tests/pipeline/config.yaml").await.unwrap();
+    assert_eq!(context.runtime().action_pipeline(), ["media", "markdown"]);
+    assert_eq!(context.runtime().game_pipeline(), ["media"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn explicit() {
+    let context = open("tests/pipeline/explicit.yaml").await.unwrap();
+    assert_eq!(context.runtime().action_pipeline(), ["markdown", "media"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn invalid() {
+    assert!(open("tests/pipeline/invalid.yaml").await.is_err());
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/random/config.yaml";
+
+async fn open(seed: u64) -> Context<WasmiModule> {
+    open_with(builder().seed(seed), CONFIG_PATH).await.unwrap()
+}
+
+fn run(context: &mut Context<WasmiModule>) -> Vec<(RawContext, String)> {
+    let loc = locale!("en");
+    let mut lines = vec![];
+    while let Some(raw_ctx) = context.next_run() {
+        if let Action::Text(text) = context.get_action(&loc, &raw_ctx).unwrap() {
+            lines.push((raw_ctx, text.to_string()));
+        }
+    }
+    lines
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn fixed_seed() {
+    let mut context = open(42).await;
+    context.set_start_context();
+    let texts = run(&mut context)
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect::<Vec<_>>();
+    assert_eq!(texts.len(), 2);
+
+    context.set_start_context();
+    let replay = run(&mut context)
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect::<Vec<_>>();
+    assert_eq!(texts, replay);
+
+    let mut context = open(43).await;
+    context.set_start_context();
+    let other = run(&mut context)
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect::<Vec<_>>();
+    assert_ne!(texts, other);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn record() {
+    let mut context = open(42).await;
+    context.set_start_context();
+    let lines = run(&mut context);
+    assert_eq!(lines[0].0.rng.count, 1);
+
+    // Resume from a saved record, like `GameViewModel::init_context`.
+    let record = serde_json::to_string(&lines[0].0).unwrap();
+    let mut ctx: RawContext = serde_json::from_str(&record).unwrap();
+    ctx.cur_act += 1;
+    context.set_context(ctx);
+    let resumed = run(&mut context);
+    assert_eq!(resumed.len(), 1);
+    assert_eq!(resumed[0].1, lines[1].1);
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+use std::path::Path;
+use tempfile::tempdir;
+
+const CONFIG: &str = r#"
+title: Reload
+author: Berrysoft
+base_lang: en
+paras: paras
+start: init
+"#;
+
+fn write_paras(root: &Path, paras: &str) {
+    std::fs::write(root.join("paras").join("en").join("init.yaml"), paras).unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn reload() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(root.join("config.yaml"), CONFIG).unwrap();
+    std::fs::create_dir_all(root.join("paras").join("en")).unwrap();
+    write_paras(
+        root,
+        r#"
+- tag: init
+  texts:
+    - 0
+  next: second
+- tag: second
+  texts:
+    - 1
+    - 2
+"#,
+    );
+
+    let mut context = open(root.join("config.yaml")).await;
+    context.set_start_context();
+    let loc = locale!("en");
+    let mut next_action = || {
+        let raw_ctx = context.next_run().unwrap();
+        context.get_action(&loc, &raw_ctx).unwrap()
+    };
+    assert_eq!(next_action(), text_chars("0"));
+    assert_eq!(next_action(), text_chars("1"));
+
+    // The text changes, and the position is kept.
+    write_paras(
+        root,
+        r#"
+- tag: init
+  texts:
+    - 0
+  next: second
+- tag: second
+  texts:
+    - a
+    - b
+"#,
+    );
+    assert!(context.reload().unwrap());
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_para, "second");
+    assert_eq!(raw_ctx.cur_act, 1);
+    assert_eq!(context.get_action(&loc, &raw_ctx).unwrap(), text_chars("b"));
+
+    // The current paragraph is removed, and the game restarts.
+    write_paras(
+        root,
+        r#"
+- tag: init
+  texts:
+    - x
+"#,
+    );
+    assert!(!context.reload().unwrap());
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_para, "init");
+    assert_eq!(raw_ctx.cur_act, 0);
+    assert_eq!(context.get_action(&loc, &raw_ctx).unwrap(), text_chars("x"));
+    assert!(context.next_run().is_none());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn reload_call_stack() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(root.join("config.yaml"), CONFIG).unwrap();
+    std::fs::create_dir_all(root.join("paras").join("en")).unwrap();
+    write_paras(
+        root,
+        r#"
+- tag: init
+  texts:
+    - call: sub
+    - 0
+- tag: sub
+  texts:
+    - 1
+    - 2
+"#,
+    );
+
+    let mut context = open(root.join("config.yaml")).await;
+    context.set_start_context();
+    context.next_run().unwrap();
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_para, "sub");
+
+    // The paragraph to return to is removed, and the game restarts.
+    write_paras(
+        root,
+        r#"
+- tag: start
+  texts:
+    - x
+- tag: sub
+  texts:
+    - 1
+    - 2
+"#,
+    );
+    assert!(!context.reload().unwrap());
+    assert!(context.next_run().is_none());
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/state/config.yaml";
+
+fn run(context: &mut Context<WasmiModule>) -> Vec<String> {
+    let loc = locale!("en");
+    let mut texts = vec![];
+    context.set_start_context();
+    while let Some(raw_ctx) = context.next_run() {
+        if let Action::Text(text) = context.get_action(&loc, &raw_ctx).unwrap() {
+            texts.push(text.to_string());
+        }
+    }
+    texts
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn save_load() {
+    let mut context = open(CONFIG_PATH).await;
+    assert_eq!(run(&mut context), ["1"]);
+    let states = context.plugin_states();
+    assert!(states.contains_key("scope"));
+
+    // The state is kept in a new runtime.
+    let mut context = open(CONFIG_PATH).await;
+    context.set_plugin_states(&states);
+    assert_eq!(run(&mut context), ["2"]);
+
+    let mut context = open(CONFIG_PATH).await;
+    assert_eq!(run(&mut context), ["1"]);
+
+    // The state is reset without the data, e.g., for a new game.
+    context.set_plugin_states(&Default::default());
+    assert_eq!(run(&mut context), ["1"]);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/switches/config.yaml";
+
+fn switch(text: &str, enabled: bool, hidden: bool) -> Switch {
+    Switch {
+        text: text.to_string(),
+        enabled,
+        hidden,
+        timeout: None,
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn declarative() {
+    let mut context = open(CONFIG_PATH).await;
+    context.set_start_context();
+    let loc = locale!("en");
+
+    context.next_run().unwrap();
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        Action::Switches(vec![
+            switch("Plain", true, false),
+            switch("Disabled", false, false),
+            switch("Hidden", true, true),
+            switch("Goto", true, false),
+        ])
+    );
+
+    // A stale choice of a disabled or hidden item fails.
+    for i in [1, 2, 4] {
+        assert!(context.switch(i).is_err());
+    }
+    context.switch(3).unwrap();
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(raw_ctx.cur_para, "other");
+    assert_eq!(raw_ctx.locals.get("?"), Some(&RawValue::Num(3)));
+    assert!(!raw_ctx.locals.contains_key("0"));
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        text_chars("Other")
+    );
+
+    // The timeout is only set for the default item.
+    let raw_ctx = context.next_run().unwrap();
+    let mut default = switch("B", true, false);
+    default.timeout = Some(1000);
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        Action::Switches(vec![switch("A", true, false), default])
+    );
+    assert_eq!(context.switch_timeout().unwrap(), Some(1));
+    let raw_ctx = context.next_run().unwrap();
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        text_chars("1true")
+    );
+}
+
+#[test]
+fn serialize() {
+    // The switches are still a list, and the timeout is an optional field of the items.
+    let mut item = switch("A", true, false);
+    item.timeout = Some(1000);
+    let action = Action::Switches(vec![item, switch("B", true, false)]);
+    assert_eq!(
+        serde_json::to_value(&action).unwrap(),
+        serde_json::json!({
+            "type": "Switches",
+            "data": [
+                { "text": "A", "enabled": true, "hidden": false, "timeout": 1000 },
+                { "text": "B", "enabled": true, "hidden": false, "timeout": null },
+            ]
+        })
+    );
+    // The old data without timeout is still accepted.
+    let old: Action = serde_json::from_value(serde_json::json!({
+        "type": "Switches",
+        "data": [{ "text": "B", "enabled": true, "hidden": false }]
+    }))
+    .unwrap();
+    assert_eq!(old, Action::Switches(vec![switch("B", true, false)]));
+}
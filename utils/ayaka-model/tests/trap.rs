@@ -0,0 +1,38 @@
+mod common;
+
+use ayaka_model::*;
+use common::*;
+
+const CONFIG_PATH: &str = "tests/trap/config.yaml";
+const FAIL_CONFIG_PATH: &str = "tests/trap/fail.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn skip() {
+    let mut context = open(CONFIG_PATH).await;
+    let module = context.runtime().text_module("ruby").unwrap();
+    assert_eq!(module.trap_policy(), TrapPolicy::Skip);
+    context.set_start_context();
+    let loc = locale!("en");
+    let actions = actions(&mut context, &loc);
+    // The command with wrong args outputs nothing.
+    assert_eq!(actions, [text_chars("World"), text_chars("Ruby（ruby）")]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn fail() {
+    let mut context = open(FAIL_CONFIG_PATH).await;
+    let module = context.runtime().text_module("ruby").unwrap();
+    assert_eq!(module.trap_policy(), TrapPolicy::Fail);
+    context.set_start_context();
+    let loc = locale!("en");
+    let actions = actions(&mut context, &loc);
+    // The called tag traps, and the run stops before the call.
+    assert_eq!(actions, [text_chars("Hello")]);
+    let diagnostics = context.take_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].kind, DiagnosticKind::PluginFailure);
+    assert_eq!(diagnostics[0].act, 1);
+    // The run keeps stopping at the failed line.
+    assert!(context.next_run().is_none());
+    assert_eq!(context.take_diagnostics().len(), 1);
+}
@@ -1,90 +1,222 @@
 //! Wasmi-based plugin backend.
 
 #![warn(missing_docs)]
+#![deny(unsafe_code)]
 
 use ayaka_plugin::*;
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use wasmi::{
+    core::{LimiterError, ResourceLimiter, TrapCode},
+    *,
 };
-use wasmi::*;
 
-unsafe fn mem_slice<'a, T: 'a>(
+fn mem_slice<'a, T: 'a>(
     store: impl Into<StoreContext<'a, T>>,
     memory: &Memory,
     start: i32,
     len: i32,
-) -> &'a [u8] {
-    memory
-        .data(store)
-        .get_unchecked(start as usize..)
-        .get_unchecked(..len as usize)
+) -> Result<&'a [u8], wasmi::Error> {
+    mem_range(start, len)
+        .and_then(|range| memory.data(store).get(range))
+        .ok_or_else(|| out_of_bounds(start, len))
 }
 
-unsafe fn mem_slice_mut<'a, T: 'a>(
+fn mem_slice_mut<'a, T: 'a>(
     store: impl Into<StoreContextMut<'a, T>>,
     memory: &Memory,
     start: i32,
     len: i32,
-) -> &'a mut [u8] {
-    memory
-        .data_mut(store)
-        .get_unchecked_mut(start as usize..)
-        .get_unchecked_mut(..len as usize)
+) -> Result<&'a mut [u8], wasmi::Error> {
+    mem_range(start, len)
+        .and_then(|range| memory.data_mut(store).get_mut(range))
+        .ok_or_else(|| out_of_bounds(start, len))
 }
 
-type HostStore = Arc<Mutex<Store<()>>>;
+fn out_of_bounds(start: i32, len: i32) -> wasmi::Error {
+    wasmi::Error::new(format!(
+        "memory access out of bounds: {} bytes at {}",
+        len as u32, start as u32
+    ))
+}
 
-/// A Wasmi [`Instance`].
-pub struct WasmiModule {
-    store: HostStore,
+/// The data of the [`Store`].
+struct HostState {
+    limits: ResourceLimits,
+}
+
+impl ResourceLimiter for HostState {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool, LimiterError> {
+        Ok(self.limits.memory_growing(desired))
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool, LimiterError> {
+        Ok(self.limits.table_growing(desired))
+    }
+
+    // Every store holds only one instance of a plugin,
+    // and a plugin owns one memory and one table.
+
+    fn instances(&self) -> usize {
+        1
+    }
+
+    fn tables(&self) -> usize {
+        1
+    }
+
+    fn memories(&self) -> usize {
+        1
+    }
+}
+
+/// The exports of an [`Instance`].
+#[derive(Clone)]
+struct ModuleInstance {
     instance: Instance,
     memory: Memory,
     abi_free: TypedFunc<(i32, i32), ()>,
     abi_alloc: TypedFunc<i32, i32>,
 }
 
-impl WasmiModule {
-    fn new(store: HostStore, module: &Module, linker: &wasmi::Linker<()>) -> Result<Self> {
-        let mut inner_store = store.lock().unwrap();
-        let instance = linker
-            .instantiate(inner_store.as_context_mut(), module)?
-            .start(inner_store.as_context_mut())?;
+impl ModuleInstance {
+    fn new(
+        mut store: StoreContextMut<HostState>,
+        module: &Module,
+        linker: &wasmi::Linker<HostState>,
+    ) -> Result<Self> {
+        let instance = linker.instantiate(&mut store, module)?.start(&mut store)?;
         let memory = instance
-            .get_export(inner_store.as_context(), MEMORY_NAME)
+            .get_export(&store, MEMORY_NAME)
             .ok_or_else(|| anyhow!("cannot get memory"))?
             .into_memory()
             .ok_or_else(|| anyhow!("memory is not Memory"))?;
         let abi_free = instance
-            .get_export(inner_store.as_context(), ABI_FREE_NAME)
+            .get_export(&store, ABI_FREE_NAME)
             .ok_or_else(|| anyhow!("cannot get abi_free"))?
             .into_func()
             .ok_or_else(|| anyhow!("abi_free is not Func"))?
-            .typed(inner_store.as_context())?;
+            .typed(&store)?;
         let abi_alloc = instance
-            .get_export(inner_store.as_context_mut(), ABI_ALLOC_NAME)
+            .get_export(&store, ABI_ALLOC_NAME)
             .ok_or_else(|| anyhow!("cannot get abi_alloc"))?
             .into_func()
             .ok_or_else(|| anyhow!("abi_alloc is not Func"))?
-            .typed(inner_store.as_context())?;
-        drop(inner_store);
+            .typed(&store)?;
         Ok(Self {
-            store,
             instance,
             memory,
             abi_free,
             abi_alloc,
         })
     }
+}
+
+/// The [`Store`] owned by a module, with the only instance in it.
+struct ModuleStore {
+    store: Store<HostState>,
+    instance: ModuleInstance,
+}
+
+/// A Wasmi [`Instance`] in its own [`Store`].
+///
+/// The store is dropped and created again with the same imports after a trap.
+pub struct WasmiModule {
+    name: String,
+    fuel: Option<u64>,
+    limits: ResourceLimits,
+    module: Module,
+    linker: wasmi::Linker<HostState>,
+    store: Mutex<ModuleStore>,
+    poisoned: AtomicBool,
+}
+
+impl WasmiModule {
+    fn new(
+        name: &str,
+        fuel: Option<u64>,
+        limits: ResourceLimits,
+        module: Module,
+        linker: wasmi::Linker<HostState>,
+    ) -> Result<Self> {
+        let store = Self::instantiate(fuel, &limits, &module, &linker)?;
+        Ok(Self {
+            name: name.to_string(),
+            fuel,
+            limits,
+            module,
+            linker,
+            store: Mutex::new(store),
+            poisoned: AtomicBool::new(false),
+        })
+    }
+
+    fn instantiate(
+        fuel: Option<u64>,
+        limits: &ResourceLimits,
+        module: &Module,
+        linker: &wasmi::Linker<HostState>,
+    ) -> Result<ModuleStore> {
+        let mut store = Store::new(
+            module.engine(),
+            HostState {
+                limits: limits.clone(),
+            },
+        );
+        store.limiter(|state| state);
+        if let Some(fuel) = fuel {
+            store.set_fuel(fuel)?;
+        }
+        let instance = ModuleInstance::new(store.as_context_mut(), module, linker)?;
+        Ok(ModuleStore { store, instance })
+    }
+
+    /// Calls a method in the store,
+    /// and creates the store again if poisoned.
+    fn call_store<T>(
+        &self,
+        store: &mut ModuleStore,
+        name: &str,
+        data: &[u8],
+        f: impl FnOnce(&[u8]) -> Result<T>,
+    ) -> Result<T> {
+        if self.poisoned.load(Ordering::Acquire) {
+            // The old store is dropped with all its memory.
+            *store = Self::instantiate(self.fuel, &self.limits, &self.module, &self.linker)?;
+            self.poisoned.store(false, Ordering::Release);
+        }
+        let ModuleStore { store, instance } = store;
+        if let Some(fuel) = self.fuel {
+            store.set_fuel(fuel)?;
+        }
+        store.data_mut().limits.take_exceeded();
+        self.call_impl(store.as_context_mut(), instance, name, data, f)
+    }
 
     fn call_impl<T>(
         &self,
-        mut store: StoreContextMut<()>,
+        mut store: StoreContextMut<HostState>,
+        instance: &ModuleInstance,
         name: &str,
         data: &[u8],
         f: impl FnOnce(&[u8]) -> Result<T>,
     ) -> Result<T> {
-        let func = self
+        let func = instance
             .instance
             .get_export(&store, name)
             .ok_or_else(|| anyhow!("cannot get export {}", name))?
@@ -92,27 +224,59 @@ impl WasmiModule {
             .ok_or_else(|| anyhow!("{} is not Func", name))?
             .typed::<(i32, i32), u64>(&store)?;
 
-        let ptr = self.abi_alloc.call(&mut store, data.len() as i32)?;
-        unsafe {
-            mem_slice_mut(&mut store, &self.memory, ptr, data.len() as i32).copy_from_slice(data)
-        };
+        let ptr = instance
+            .abi_alloc
+            .call(&mut store, data.len() as i32)
+            .map_err(|e| self.map_error(&mut store, name, e))?;
+        match mem_slice_mut(&mut store, &instance.memory, ptr, data.len() as i32) {
+            Ok(slice) => slice.copy_from_slice(data),
+            Err(e) => return Err(self.map_error(&mut store, name, e)),
+        }
 
-        let res = func.call(&mut store, (data.len() as i32, ptr));
+        // The memory is dropped with the poisoned store if it traps.
+        let res = func
+            .call(&mut store, (data.len() as i32, ptr))
+            .map_err(|e| self.map_error(&mut store, name, e))?;
 
-        self.abi_free.call(&mut store, (ptr, data.len() as i32))?;
+        instance
+            .abi_free
+            .call(&mut store, (ptr, data.len() as i32))
+            .map_err(|e| self.map_error(&mut store, name, e))?;
 
-        let res = res?;
         let (len, res) = ((res >> 32) as i32, (res & 0xFFFFFFFF) as i32);
 
-        let res_data = unsafe { mem_slice(&store, &self.memory, res, len) };
-
-        let res_data = f(res_data);
+        let res_data = match mem_slice(&store, &instance.memory, res, len) {
+            Ok(res_data) => f(res_data),
+            Err(e) => return Err(self.map_error(&mut store, name, e)),
+        };
 
-        self.abi_free.call(&mut store, (res, len))?;
+        instance
+            .abi_free
+            .call(&mut store, (res, len))
+            .map_err(|e| self.map_error(&mut store, name, e))?;
 
         let res_data = res_data?;
         Ok(res_data)
     }
+
+    /// Maps an error of executing the module to [`PluginError`],
+    /// and marks the store poisoned.
+    fn map_error(
+        &self,
+        store: &mut StoreContextMut<HostState>,
+        name: &str,
+        e: wasmi::Error,
+    ) -> anyhow::Error {
+        self.poisoned.store(true, Ordering::Release);
+        let kind = if e.as_trap_code() == Some(TrapCode::OutOfFuel) {
+            PluginErrorKind::OutOfFuel
+        } else if let Some(kind) = store.data_mut().limits.take_exceeded() {
+            kind
+        } else {
+            PluginErrorKind::Trap(e.to_string())
+        };
+        PluginError::new(&self.name, name, kind).into()
+    }
 }
 
 impl RawModule for WasmiModule {
@@ -120,84 +284,119 @@ impl RawModule for WasmiModule {
 
     type LinkerHandle<'a> = WasmiLinkerHandle<'a>;
 
-    type Func = Func;
+    type Func = HostFunc;
 
     fn call<T>(&self, name: &str, data: &[u8], f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
-        self.call_impl(self.store.lock().unwrap().as_context_mut(), name, data, f)
+        let mut store = self.store.lock().unwrap();
+        self.call_store(&mut store, name, data, f)
     }
 }
 
-/// A Wasmi [`Store`] with [`Linker`].
+type RawHostFunc = dyn (Fn(WasmiLinkerHandle, i32, i32) -> Result<Vec<u8>>) + Send + Sync;
+
+/// A host function, linked into the modules created after imported.
+#[derive(Clone)]
+pub struct HostFunc(Arc<RawHostFunc>);
+
+/// A Wasmi [`Linker`].
+///
+/// Every module is instantiated in its own [`Store`].
+///
+/// [`LinkerConfig::timeout`] is not supported, and a config with it is rejected.
+/// [`LinkerConfig::cache_dir`] is not supported and ignored.
 pub struct WasmiLinker {
+    fuel: Option<u64>,
+    default_limits: ResourceLimits,
+    limits: ResourceLimits,
     engine: Engine,
-    store: HostStore,
-    linker: wasmi::Linker<()>,
+    linker: wasmi::Linker<HostState>,
 }
 
 impl ayaka_plugin::Linker<WasmiModule> for WasmiLinker {
-    type Config = ();
+    type Config = LinkerConfig;
 
-    fn new(_: ()) -> Result<Self> {
-        let engine = Engine::default();
-        let store = Store::new(&engine, ());
+    fn new(config: LinkerConfig) -> Result<Self> {
+        if config.timeout.is_some() {
+            anyhow::bail!("the timeout is not supported by Wasmi");
+        }
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(config.fuel.is_some());
+        let engine = Engine::new(&engine_config);
         let linker = wasmi::Linker::new(&engine);
         Ok(Self {
+            fuel: config.fuel,
+            default_limits: ResourceLimits::new(&config),
+            limits: ResourceLimits::new(&config),
             engine,
-            store: Arc::new(Mutex::new(store)),
             linker,
         })
     }
 
-    fn create(&self, binary: &[u8]) -> Result<WasmiModule> {
+    fn create(&self, name: &str, binary: &[u8]) -> Result<WasmiModule> {
         let module = Module::new(&self.engine, binary)?;
-        let host = WasmiModule::new(self.store.clone(), &module, &self.linker)?;
+        let host = WasmiModule::new(
+            name,
+            self.fuel,
+            self.limits.clone(),
+            module,
+            self.linker.clone(),
+        )?;
         Ok(host)
     }
 
-    fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, Func>) -> Result<()> {
+    fn set_limits(&mut self, limits: ModuleLimits) {
+        self.limits = self.default_limits.clone().with_module(&limits);
+    }
+
+    fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, HostFunc>) -> Result<()> {
         let ns = ns.into();
-        for (name, func) in funcs {
-            self.linker.define(&ns, &name, func)?;
+        for (name, HostFunc(f)) in funcs {
+            self.linker.func_wrap(
+                &ns,
+                &name,
+                move |mut store: Caller<HostState>, len: i32, data: i32| {
+                    let memory = store
+                        .get_export(MEMORY_NAME)
+                        .ok_or_else(|| wasmi::Error::new("cannot get memory"))?
+                        .into_memory()
+                        .ok_or_else(|| wasmi::Error::new("memory is not Memory"))?;
+                    let data = {
+                        let store = store.as_context_mut();
+                        let handle = WasmiLinkerHandle { store, memory };
+                        f(handle, data, len).map_err(|e| wasmi::Error::new(e.to_string()))?
+                    };
+                    let abi_alloc = store
+                        .get_export(ABI_ALLOC_NAME)
+                        .ok_or_else(|| wasmi::Error::new("cannot get abi_alloc"))?
+                        .into_func()
+                        .ok_or_else(|| wasmi::Error::new("abi_alloc is not Func"))?
+                        .typed::<i32, i32>(store.as_context())
+                        .map_err(|e| wasmi::Error::new(e.to_string()))?;
+                    let ptr = abi_alloc.call(store.as_context_mut(), data.len() as i32)?;
+                    mem_slice_mut(store.as_context_mut(), &memory, ptr, data.len() as i32)?
+                        .copy_from_slice(&data);
+                    Ok(((data.len() as u64) << 32) | (ptr as u64))
+                },
+            )?;
         }
         Ok(())
     }
 
+    fn clear_imports(&mut self) {
+        self.linker = wasmi::Linker::new(&self.engine);
+    }
+
     fn wrap_raw(
         &self,
         f: impl (Fn(WasmiLinkerHandle, i32, i32) -> Result<Vec<u8>>) + Send + Sync + 'static,
-    ) -> Func {
-        Func::wrap(
-            self.store.lock().unwrap().as_context_mut(),
-            move |mut store: Caller<()>, len: i32, data: i32| unsafe {
-                let memory = store
-                    .get_export(MEMORY_NAME)
-                    .ok_or_else(|| wasmi::Error::new("cannot get memory"))?
-                    .into_memory()
-                    .ok_or_else(|| wasmi::Error::new("memory is not Memory"))?;
-                let data = {
-                    let store = store.as_context_mut();
-                    let handle = WasmiLinkerHandle { store, memory };
-                    f(handle, data, len).map_err(|e| wasmi::Error::new(e.to_string()))?
-                };
-                let abi_alloc = store
-                    .get_export(ABI_ALLOC_NAME)
-                    .ok_or_else(|| wasmi::Error::new("cannot get abi_alloc"))?
-                    .into_func()
-                    .ok_or_else(|| wasmi::Error::new("abi_alloc is not Func"))?
-                    .typed::<i32, i32>(store.as_context())
-                    .map_err(|e| wasmi::Error::new(e.to_string()))?;
-                let ptr = abi_alloc.call(store.as_context_mut(), data.len() as i32)?;
-                mem_slice_mut(store.as_context_mut(), &memory, ptr, data.len() as i32)
-                    .copy_from_slice(&data);
-                Ok(((data.len() as u64) << 32) | (ptr as u64))
-            },
-        )
+    ) -> HostFunc {
+        HostFunc(Arc::new(f))
     }
 }
 
 /// A Wasmi [`StoreContextMut`].
 pub struct WasmiLinkerHandle<'a> {
-    store: StoreContextMut<'a, ()>,
+    store: StoreContextMut<'a, HostState>,
     memory: Memory,
 }
 
@@ -209,14 +408,28 @@ impl<'a> LinkerHandle<'a, WasmiModule> for WasmiLinkerHandle<'a> {
         data: &[u8],
         f: impl FnOnce(&[u8]) -> Result<T>,
     ) -> Result<T> {
-        m.call_impl(self.store.as_context_mut(), name, data, f)
+        let mut store = m
+            .store
+            .try_lock()
+            .map_err(|_| anyhow!("Plugin \"{}\" is already running", m.name))?;
+        m.call_store(&mut store, name, data, f)
     }
 
-    fn slice<T>(&self, start: i32, len: i32, f: impl FnOnce(&[u8]) -> T) -> T {
-        f(unsafe { mem_slice(self.store.as_context(), &self.memory, start, len) })
+    fn slice<T>(&self, start: i32, len: i32, f: impl FnOnce(&[u8]) -> T) -> Result<T> {
+        Ok(f(mem_slice(
+            self.store.as_context(),
+            &self.memory,
+            start,
+            len,
+        )?))
     }
 
-    fn slice_mut<T>(&mut self, start: i32, len: i32, f: impl FnOnce(&mut [u8]) -> T) -> T {
-        f(unsafe { mem_slice_mut(self.store.as_context_mut(), &self.memory, start, len) })
+    fn slice_mut<T>(&mut self, start: i32, len: i32, f: impl FnOnce(&mut [u8]) -> T) -> Result<T> {
+        Ok(f(mem_slice_mut(
+            self.store.as_context_mut(),
+            &self.memory,
+            start,
+            len,
+        )?))
     }
 }
@@ -3,98 +3,424 @@
 #![warn(missing_docs)]
 
 use ayaka_plugin::*;
+use sha2::{Digest, Sha256};
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    hash::{Hash, Hasher},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{sync_channel, Receiver},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 use wasmtime::*;
 
-unsafe fn mem_slice<'a, T: 'static>(
+/// The interval to increase the epoch.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Increases the epoch of the engine periodically,
+/// until dropped.
+struct EpochTicker {
+    stop: Arc<AtomicBool>,
+}
+
+impl EpochTicker {
+    fn new(engine: Engine) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(EPOCH_TICK);
+                engine.increment_epoch();
+            }
+        });
+        Self { stop }
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The data of the [`Store`].
+struct HostState {
+    limits: ResourceLimits,
+}
+
+impl ResourceLimiter for HostState {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(self.limits.memory_growing(desired))
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(self.limits.table_growing(desired))
+    }
+
+    // Every store holds only one instance of a plugin,
+    // and a plugin owns one memory and one table.
+
+    fn instances(&self) -> usize {
+        1
+    }
+
+    fn tables(&self) -> usize {
+        1
+    }
+
+    fn memories(&self) -> usize {
+        1
+    }
+}
+
+/// The budget of a call from the host.
+#[derive(Clone)]
+struct Budget {
+    fuel: Option<u64>,
+    ticks: Option<u64>,
+    _ticker: Option<Arc<EpochTicker>>,
+}
+
+impl Budget {
+    fn new(engine: &Engine, config: &LinkerConfig) -> Self {
+        let ticks = config
+            .timeout
+            .map(|timeout| (timeout.as_millis() / EPOCH_TICK.as_millis()).max(1) as u64);
+        Self {
+            fuel: config.fuel,
+            ticks,
+            _ticker: ticks.map(|_| Arc::new(EpochTicker::new(engine.clone()))),
+        }
+    }
+
+    fn reset(&self, mut store: StoreContextMut<HostState>) -> Result<()> {
+        if let Some(fuel) = self.fuel {
+            store.set_fuel(fuel)?;
+        }
+        if let Some(ticks) = self.ticks {
+            store.set_epoch_deadline(ticks);
+        }
+        Ok(())
+    }
+}
+
+fn mem_slice<'a, T: 'static>(
     store: impl Into<StoreContext<'a, T>>,
     memory: &Memory,
     start: i32,
     len: i32,
-) -> &'a [u8] {
-    memory
-        .data(store)
-        .get_unchecked(start as usize..)
-        .get_unchecked(..len as usize)
+) -> Result<&'a [u8]> {
+    mem_range(start, len)
+        .and_then(|range| memory.data(store).get(range))
+        .ok_or_else(|| out_of_bounds(start, len))
 }
 
-unsafe fn mem_slice_mut<'a, T: 'static>(
+fn mem_slice_mut<'a, T: 'static>(
     store: impl Into<StoreContextMut<'a, T>>,
     memory: &Memory,
     start: i32,
     len: i32,
-) -> &'a mut [u8] {
-    memory
-        .data_mut(store)
-        .get_unchecked_mut(start as usize..)
-        .get_unchecked_mut(..len as usize)
+) -> Result<&'a mut [u8]> {
+    mem_range(start, len)
+        .and_then(|range| memory.data_mut(store).get_mut(range))
+        .ok_or_else(|| out_of_bounds(start, len))
+}
+
+fn out_of_bounds(start: i32, len: i32) -> anyhow::Error {
+    anyhow!(
+        "memory access out of bounds: {} bytes at {}",
+        len as u32,
+        start as u32
+    )
 }
 
-type HostStore = Arc<Mutex<Store<()>>>;
+/// Feeds a [`Hash`] into [`Sha256`].
+///
+/// [`Hasher::finish`] returns the first 8 bytes of the current digest.
+struct Sha256Hasher(Sha256);
 
-/// A Wasmtime [`Instance`].
-pub struct WasmtimeModule {
-    store: HostStore,
+impl Hasher for Sha256Hasher {
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+/// The key of a compiled module in hex,
+/// the SHA-256 digest of the binary and the config of the engine.
+fn module_key(engine: &Engine, binary: &[u8]) -> String {
+    let mut hasher = Sha256Hasher(Sha256::new());
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    hasher.0.update(binary);
+    hex(&hasher.0.finalize())
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Compiles a module, or loads it from the cache directory.
+fn compile(engine: &Engine, cache_dir: Option<&Path>, key: &str, binary: &[u8]) -> Result<Module> {
+    let Some(cache_dir) = cache_dir else {
+        return Module::new(engine, binary);
+    };
+    let path = cache_dir.join(format!("{key}.cwasm"));
+    if let Some(data) = read_cache(&path) {
+        // SAFETY: Wasmtime checks the header and the engine config of the data,
+        // but it does not validate the compiled code.
+        // A tampered file could run arbitrary native code,
+        // so the cache directory must be trusted, as documented in `LinkerConfig::cache_dir`.
+        // The key covers the binary and the engine config,
+        // so the data is only loaded for the same module compiled by a compatible engine,
+        // and the digest check rejects the files not written by `write_cache` completely.
+        if let Ok(module) = unsafe { Module::deserialize(engine, &data) } {
+            return Ok(module);
+        }
+    }
+    let module = Module::new(engine, binary)?;
+    // The cache is optional, so it is fine if it fails to write.
+    write_cache(&path, &module).ok();
+    Ok(module)
+}
+
+/// The path of the SHA-256 digest of a cached module.
+fn digest_path(path: &Path) -> PathBuf {
+    path.with_extension("sha256")
+}
+
+/// Reads a cached module,
+/// if its SHA-256 digest matches the one written with it.
+fn read_cache(path: &Path) -> Option<Vec<u8>> {
+    let digest = std::fs::read_to_string(digest_path(path)).ok()?;
+    let data = std::fs::read(path).ok()?;
+    if hex(&Sha256::digest(&data)) == digest.trim() {
+        Some(data)
+    } else {
+        None
+    }
+}
+
+/// The count of the temp files written by this process.
+static TEMP_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Writes the module and its digest to the cache.
+fn write_cache(path: &Path, module: &Module) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let data = module.serialize()?;
+    write_file(&digest_path(path), hex(&Sha256::digest(&data)).as_bytes())?;
+    write_file(path, &data)?;
+    Ok(())
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    // Write to a temp file first, so that a partial file is never loaded.
+    // The name is unique, so that the games sharing the directory never write the same file.
+    let temp_path = path.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let res = std::fs::write(&temp_path, data).and_then(|()| std::fs::rename(&temp_path, path));
+    if res.is_err() {
+        std::fs::remove_file(&temp_path).ok();
+    }
+    res?;
+    Ok(())
+}
+
+/// The exports of an [`Instance`].
+#[derive(Clone)]
+struct ModuleInstance {
     instance: Instance,
     memory: Memory,
     abi_free: TypedFunc<(i32, i32), ()>,
     abi_alloc: TypedFunc<i32, i32>,
 }
 
-impl WasmtimeModule {
-    fn new(store: HostStore, module: &Module, linker: &wasmtime::Linker<()>) -> Result<Self> {
-        let mut inner_store = store.lock().unwrap();
-        let instance = linker.instantiate(inner_store.as_context_mut(), module)?;
+impl ModuleInstance {
+    fn new(
+        mut store: StoreContextMut<HostState>,
+        module: &Module,
+        linker: &wasmtime::Linker<HostState>,
+    ) -> Result<Self> {
+        let instance = linker.instantiate(&mut store, module)?;
         let memory = instance
-            .get_memory(inner_store.as_context_mut(), MEMORY_NAME)
+            .get_memory(&mut store, MEMORY_NAME)
             .ok_or_else(|| anyhow!("cannot get memory"))?;
-        let abi_free = instance.get_typed_func(inner_store.as_context_mut(), ABI_FREE_NAME)?;
-        let abi_alloc = instance.get_typed_func(inner_store.as_context_mut(), ABI_ALLOC_NAME)?;
-        drop(inner_store);
+        let abi_free = instance.get_typed_func(&mut store, ABI_FREE_NAME)?;
+        let abi_alloc = instance.get_typed_func(&mut store, ABI_ALLOC_NAME)?;
         Ok(Self {
-            store,
             instance,
             memory,
             abi_free,
             abi_alloc,
         })
     }
+}
+
+/// The [`Store`] owned by a module, with the only instance in it.
+struct ModuleStore {
+    store: Store<HostState>,
+    instance: ModuleInstance,
+}
+
+/// A Wasmtime [`Instance`] in its own [`Store`].
+///
+/// The store is dropped and created again with the same imports after a trap.
+pub struct WasmtimeModule {
+    name: String,
+    budget: Budget,
+    limits: ResourceLimits,
+    module: Module,
+    linker: wasmtime::Linker<HostState>,
+    store: Mutex<ModuleStore>,
+    poisoned: AtomicBool,
+}
+
+impl WasmtimeModule {
+    fn new(
+        name: &str,
+        budget: Budget,
+        limits: ResourceLimits,
+        module: Module,
+        linker: wasmtime::Linker<HostState>,
+    ) -> Result<Self> {
+        let store = Self::instantiate(&budget, &limits, &module, &linker)?;
+        Ok(Self {
+            name: name.to_string(),
+            budget,
+            limits,
+            module,
+            linker,
+            store: Mutex::new(store),
+            poisoned: AtomicBool::new(false),
+        })
+    }
+
+    fn instantiate(
+        budget: &Budget,
+        limits: &ResourceLimits,
+        module: &Module,
+        linker: &wasmtime::Linker<HostState>,
+    ) -> Result<ModuleStore> {
+        let mut store = Store::new(
+            module.engine(),
+            HostState {
+                limits: limits.clone(),
+            },
+        );
+        store.limiter(|state| state);
+        budget.reset(store.as_context_mut())?;
+        let instance = ModuleInstance::new(store.as_context_mut(), module, linker)?;
+        Ok(ModuleStore { store, instance })
+    }
+
+    /// Calls a method in the store,
+    /// and creates the store again if poisoned.
+    fn call_store<T>(
+        &self,
+        store: &mut ModuleStore,
+        name: &str,
+        data: &[u8],
+        f: impl FnOnce(&[u8]) -> Result<T>,
+    ) -> Result<T> {
+        if self.poisoned.load(Ordering::Acquire) {
+            // The old store is dropped with all its memory.
+            *store = Self::instantiate(&self.budget, &self.limits, &self.module, &self.linker)?;
+            self.poisoned.store(false, Ordering::Release);
+        }
+        let ModuleStore { store, instance } = store;
+        self.budget.reset(store.as_context_mut())?;
+        store.data_mut().limits.take_exceeded();
+        self.call_impl(store.as_context_mut(), instance, name, data, f)
+    }
 
     fn call_impl<T>(
         &self,
-        mut store: StoreContextMut<()>,
+        mut store: StoreContextMut<HostState>,
+        instance: &ModuleInstance,
         name: &str,
         data: &[u8],
         f: impl FnOnce(&[u8]) -> Result<T>,
     ) -> Result<T> {
-        let func = self
+        let func = instance
             .instance
             .get_typed_func::<(i32, i32), u64>(&mut store, name)?;
 
-        let ptr = self.abi_alloc.call(&mut store, data.len() as i32)?;
-        unsafe {
-            mem_slice_mut(&mut store, &self.memory, ptr, data.len() as i32).copy_from_slice(data)
-        };
+        let ptr = instance
+            .abi_alloc
+            .call(&mut store, data.len() as i32)
+            .map_err(|e| self.map_error(&mut store, name, e))?;
+        match mem_slice_mut(&mut store, &instance.memory, ptr, data.len() as i32) {
+            Ok(slice) => slice.copy_from_slice(data),
+            Err(e) => return Err(self.map_error(&mut store, name, e)),
+        }
 
-        let res = func.call(&mut store, (data.len() as i32, ptr));
+        // The memory is dropped with the poisoned store if it traps.
+        let res = func
+            .call(&mut store, (data.len() as i32, ptr))
+            .map_err(|e| self.map_error(&mut store, name, e))?;
 
-        self.abi_free.call(&mut store, (ptr, data.len() as i32))?;
+        instance
+            .abi_free
+            .call(&mut store, (ptr, data.len() as i32))
+            .map_err(|e| self.map_error(&mut store, name, e))?;
 
-        let res = res?;
         let (len, res) = ((res >> 32) as i32, (res & 0xFFFFFFFF) as i32);
 
-        let res_data = unsafe { mem_slice(&store, &self.memory, res, len) };
-
-        let res_data = f(res_data);
+        let res_data = match mem_slice(&store, &instance.memory, res, len) {
+            Ok(res_data) => f(res_data),
+            Err(e) => return Err(self.map_error(&mut store, name, e)),
+        };
 
-        self.abi_free.call(&mut store, (res, len))?;
+        instance
+            .abi_free
+            .call(&mut store, (res, len))
+            .map_err(|e| self.map_error(&mut store, name, e))?;
 
         let res_data = res_data?;
         Ok(res_data)
     }
+
+    /// Maps an error of executing the module to [`PluginError`],
+    /// and marks the store poisoned.
+    fn map_error(
+        &self,
+        store: &mut StoreContextMut<HostState>,
+        name: &str,
+        e: anyhow::Error,
+    ) -> anyhow::Error {
+        self.poisoned.store(true, Ordering::Release);
+        let kind = match e.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => PluginErrorKind::OutOfFuel,
+            Some(Trap::Interrupt) => PluginErrorKind::Timeout,
+            _ => match store.data_mut().limits.take_exceeded() {
+                Some(kind) => kind,
+                None => PluginErrorKind::Trap(e.root_cause().to_string()),
+            },
+        };
+        PluginError::new(&self.name, name, kind).into()
+    }
 }
 
 impl RawModule for WasmtimeModule {
@@ -102,84 +428,155 @@ impl RawModule for WasmtimeModule {
 
     type LinkerHandle<'a> = WasmtimeLinkerHandle<'a>;
 
-    type Func = Func;
+    type Func = HostFunc;
 
     fn call<T>(&self, name: &str, data: &[u8], f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
-        self.call_impl(self.store.lock().unwrap().as_context_mut(), name, data, f)
+        let mut store = self.store.lock().unwrap();
+        self.call_store(&mut store, name, data, f)
     }
 }
 
-/// A Wasmtime [`Store`] with [`Linker`].
+type RawHostFunc = dyn (Fn(WasmtimeLinkerHandle<'_>, i32, i32) -> Result<Vec<u8>>) + Send + Sync;
+
+/// A host function, linked into the modules created after imported.
+#[derive(Clone)]
+pub struct HostFunc(Arc<RawHostFunc>);
+
+/// A Wasmtime [`Linker`].
+///
+/// Every module is instantiated in its own [`Store`].
+/// The timeout is checked by epoch interruption,
+/// with a precision of about 10 milliseconds.
+///
+/// The modules are compiled in parallel if prepared,
+/// in the global thread pool of [`rayon`], bounded by the count of the CPUs,
+/// and cached with [`Module::serialize`] if the cache directory is set.
 pub struct WasmtimeLinker {
+    budget: Budget,
+    default_limits: ResourceLimits,
+    limits: ResourceLimits,
     engine: Engine,
-    store: HostStore,
-    linker: wasmtime::Linker<()>,
+    cache_dir: Option<PathBuf>,
+    compiling: Mutex<HashMap<String, Receiver<Result<Module>>>>,
+    linker: wasmtime::Linker<HostState>,
 }
 
 impl ayaka_plugin::Linker<WasmtimeModule> for WasmtimeLinker {
-    type Config = ();
+    type Config = LinkerConfig;
 
-    fn new(_: ()) -> Result<Self> {
-        let engine = Engine::default();
-        let store = Store::new(&engine, ());
+    fn new(config: LinkerConfig) -> Result<Self> {
+        let mut engine_config = Config::default();
+        engine_config
+            .consume_fuel(config.fuel.is_some())
+            .epoch_interruption(config.timeout.is_some());
+        let engine = Engine::new(&engine_config)?;
+        let budget = Budget::new(&engine, &config);
         let linker = wasmtime::Linker::new(&engine);
         Ok(Self {
+            budget,
+            default_limits: ResourceLimits::new(&config),
+            limits: ResourceLimits::new(&config),
             engine,
-            store: Arc::new(Mutex::new(store)),
+            cache_dir: config.cache_dir,
+            compiling: Mutex::new(HashMap::new()),
             linker,
         })
     }
 
-    fn create(&self, binary: &[u8]) -> Result<WasmtimeModule> {
-        let module = Module::new(&self.engine, binary)?;
-        let host = WasmtimeModule::new(self.store.clone(), &module, &self.linker)?;
+    fn prepare(&self, binary: &[u8]) {
+        let key = module_key(&self.engine, binary);
+        self.compiling
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let (tx, rx) = sync_channel(1);
+                let engine = self.engine.clone();
+                let cache_dir = self.cache_dir.clone();
+                let binary = binary.to_vec();
+                rayon::spawn(move || {
+                    // A panic in the thread pool aborts the process.
+                    let res = catch_unwind(AssertUnwindSafe(|| {
+                        compile(&engine, cache_dir.as_deref(), &key, &binary)
+                    }))
+                    .unwrap_or_else(|_| Err(anyhow!("the compiling task panicked")));
+                    tx.send(res).ok();
+                });
+                rx
+            });
+    }
+
+    fn create(&self, name: &str, binary: &[u8]) -> Result<WasmtimeModule> {
+        let key = module_key(&self.engine, binary);
+        let compiling = self.compiling.lock().unwrap().remove(&key);
+        let module = match compiling {
+            Some(rx) => rx
+                .recv()
+                .map_err(|_| anyhow!("the compiling task is dropped"))??,
+            None => compile(&self.engine, self.cache_dir.as_deref(), &key, binary)?,
+        };
+        let host = WasmtimeModule::new(
+            name,
+            self.budget.clone(),
+            self.limits.clone(),
+            module,
+            self.linker.clone(),
+        )?;
         Ok(host)
     }
 
-    fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, Func>) -> Result<()> {
+    fn set_limits(&mut self, limits: ModuleLimits) {
+        self.limits = self.default_limits.clone().with_module(&limits);
+    }
+
+    fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, HostFunc>) -> Result<()> {
         let ns = ns.into();
-        let store = self.store.lock().unwrap();
-        for (name, func) in funcs {
-            self.linker.define(store.as_context(), &ns, &name, func)?;
+        for (name, HostFunc(f)) in funcs {
+            self.linker.func_wrap(
+                &ns,
+                &name,
+                move |mut store: Caller<HostState>, len: i32, data: i32| {
+                    let memory = store
+                        .get_export(MEMORY_NAME)
+                        .ok_or_else(|| anyhow!("cannot get memory"))?
+                        .into_memory()
+                        .ok_or_else(|| anyhow!("memory is not Memory"))?;
+                    let data = {
+                        let store = store.as_context_mut();
+                        let handle = WasmtimeLinkerHandle { store, memory };
+                        f(handle, data, len)?
+                    };
+                    let abi_alloc = store
+                        .get_export(ABI_ALLOC_NAME)
+                        .ok_or_else(|| anyhow!("cannot get abi_alloc"))?
+                        .into_func()
+                        .ok_or_else(|| anyhow!("abi_alloc is not Func"))?
+                        .typed::<i32, i32>(store.as_context())?;
+                    let ptr = abi_alloc.call(store.as_context_mut(), data.len() as i32)?;
+                    mem_slice_mut(store.as_context_mut(), &memory, ptr, data.len() as i32)?
+                        .copy_from_slice(&data);
+                    Ok(((data.len() as u64) << 32) | (ptr as u64))
+                },
+            )?;
         }
         Ok(())
     }
 
+    fn clear_imports(&mut self) {
+        self.linker = wasmtime::Linker::new(&self.engine);
+    }
+
     fn wrap_raw(
         &self,
         f: impl (Fn(WasmtimeLinkerHandle<'_>, i32, i32) -> Result<Vec<u8>>) + Send + Sync + 'static,
-    ) -> Func {
-        Func::wrap(
-            self.store.lock().unwrap().as_context_mut(),
-            move |mut store: Caller<()>, len: i32, data: i32| unsafe {
-                let memory = store
-                    .get_export(MEMORY_NAME)
-                    .ok_or_else(|| anyhow!("cannot get memory"))?
-                    .into_memory()
-                    .ok_or_else(|| anyhow!("memory is not Memory"))?;
-                let data = {
-                    let store = store.as_context_mut();
-                    let handle = WasmtimeLinkerHandle { store, memory };
-                    f(handle, data, len)?
-                };
-                let abi_alloc = store
-                    .get_export(ABI_ALLOC_NAME)
-                    .ok_or_else(|| anyhow!("cannot get abi_alloc"))?
-                    .into_func()
-                    .ok_or_else(|| anyhow!("abi_alloc is not Func"))?
-                    .typed::<i32, i32>(store.as_context())?;
-                let ptr = abi_alloc.call(store.as_context_mut(), data.len() as i32)?;
-                mem_slice_mut(store.as_context_mut(), &memory, ptr, data.len() as i32)
-                    .copy_from_slice(&data);
-                Ok(((data.len() as u64) << 32) | (ptr as u64))
-            },
-        )
+    ) -> HostFunc {
+        HostFunc(Arc::new(f))
     }
 }
 
 /// A Wasmtime [`StoreContextMut`].
 pub struct WasmtimeLinkerHandle<'a> {
-    store: StoreContextMut<'a, ()>,
+    store: StoreContextMut<'a, HostState>,
     memory: Memory,
 }
 
@@ -191,14 +588,28 @@ impl<'a> LinkerHandle<'a, WasmtimeModule> for WasmtimeLinkerHandle<'a> {
         data: &[u8],
         f: impl FnOnce(&[u8]) -> Result<T>,
     ) -> Result<T> {
-        m.call_impl(self.store.as_context_mut(), name, data, f)
+        let mut store = m
+            .store
+            .try_lock()
+            .map_err(|_| anyhow!("Plugin \"{}\" is already running", m.name))?;
+        m.call_store(&mut store, name, data, f)
     }
 
-    fn slice<T>(&self, start: i32, len: i32, f: impl FnOnce(&[u8]) -> T) -> T {
-        f(unsafe { mem_slice(self.store.as_context(), &self.memory, start, len) })
+    fn slice<T>(&self, start: i32, len: i32, f: impl FnOnce(&[u8]) -> T) -> Result<T> {
+        Ok(f(mem_slice(
+            self.store.as_context(),
+            &self.memory,
+            start,
+            len,
+        )?))
     }
 
-    fn slice_mut<T>(&mut self, start: i32, len: i32, f: impl FnOnce(&mut [u8]) -> T) -> T {
-        f(unsafe { mem_slice_mut(self.store.as_context_mut(), &self.memory, start, len) })
+    fn slice_mut<T>(&mut self, start: i32, len: i32, f: impl FnOnce(&mut [u8]) -> T) -> Result<T> {
+        Ok(f(mem_slice_mut(
+            self.store.as_context_mut(),
+            &self.memory,
+            start,
+            len,
+        )?))
     }
 }
@@ -11,7 +11,9 @@
 pub use anyhow::{anyhow, Result};
 
 use serde::{de::DeserializeOwned, Serialize};
-use std::{collections::HashMap, marker::Tuple};
+use std::{
+    collections::HashMap, fmt::Display, marker::Tuple, ops::Range, path::PathBuf, time::Duration,
+};
 
 /// The exported method `__abi_alloc`.
 pub const ABI_ALLOC_NAME: &str = "__abi_alloc";
@@ -20,6 +22,204 @@ pub const ABI_FREE_NAME: &str = "__abi_free";
 /// The default exported memory name.
 pub const MEMORY_NAME: &str = "memory";
 
+/// The config of the linker, mainly the execution budget of plugins.
+///
+/// The budget applies to every call into a plugin,
+/// and a nested call between plugins has a new budget of its own.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LinkerConfig {
+    /// The fuel of a call, roughly the count of executed instructions.
+    /// [`None`] means unlimited.
+    pub fuel: Option<u64>,
+    /// The time limit of a call.
+    /// [`None`] means unlimited.
+    ///
+    /// Not all backends support it,
+    /// and they fail to create the linker with it.
+    pub timeout: Option<Duration>,
+    /// The max size of the linear memory of a plugin, in bytes.
+    /// [`None`] means unlimited.
+    pub memory: Option<usize>,
+    /// The max count of elements of a table of a plugin.
+    /// [`None`] means unlimited.
+    pub table: Option<usize>,
+    /// The directory to cache the compiled modules.
+    /// [`None`] means no cache.
+    ///
+    /// The directory must be trusted,
+    /// because the cached native code is loaded without validation,
+    /// and a tampered file could run arbitrary code in the host.
+    /// The digests written with the files only reject the corrupted ones.
+    ///
+    /// Not all backends support it.
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// The resource limits of a plugin, overriding the ones in [`LinkerConfig`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleLimits {
+    /// The max size of the linear memory, in bytes.
+    /// [`None`] means the one in [`LinkerConfig`].
+    pub memory: Option<usize>,
+    /// The max count of elements of a table.
+    /// [`None`] means the one in [`LinkerConfig`].
+    pub table: Option<usize>,
+}
+
+/// The resource limits of a plugin, checked by the backends.
+///
+/// Every plugin is instantiated in its own store,
+/// so the limits apply to each plugin respectively.
+#[derive(Debug, Default, Clone)]
+pub struct ResourceLimits {
+    memory: Option<usize>,
+    table: Option<usize>,
+    exceeded: Option<PluginErrorKind>,
+}
+
+impl ResourceLimits {
+    /// Creates the limits from [`LinkerConfig`].
+    pub fn new(config: &LinkerConfig) -> Self {
+        Self {
+            memory: config.memory,
+            table: config.table,
+            exceeded: None,
+        }
+    }
+
+    /// Overrides the limits with the ones of a plugin.
+    pub fn with_module(mut self, limits: &ModuleLimits) -> Self {
+        self.memory = limits.memory.or(self.memory);
+        self.table = limits.table.or(self.table);
+        self
+    }
+
+    /// Determine if a memory could grow to the desired size.
+    pub fn memory_growing(&mut self, desired: usize) -> bool {
+        self.check(self.memory, desired, PluginErrorKind::MemoryLimit)
+    }
+
+    /// Determine if a table could grow to the desired count of elements.
+    pub fn table_growing(&mut self, desired: usize) -> bool {
+        self.check(self.table, desired, PluginErrorKind::TableLimit)
+    }
+
+    fn check(&mut self, limit: Option<usize>, desired: usize, kind: PluginErrorKind) -> bool {
+        let allowed = limit.map(|limit| desired <= limit).unwrap_or(true);
+        if !allowed {
+            self.exceeded = Some(kind);
+        }
+        allowed
+    }
+
+    /// Takes the kind of the limit exceeded since last taken.
+    pub fn take_exceeded(&mut self) -> Option<PluginErrorKind> {
+        self.exceeded.take()
+    }
+}
+
+/// The kind of [`PluginError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginErrorKind {
+    /// The fuel is exhausted.
+    OutOfFuel,
+    /// The time limit is exceeded.
+    Timeout,
+    /// The memory limit is exceeded.
+    MemoryLimit,
+    /// The table limit is exceeded.
+    TableLimit,
+    /// The plugin trapped, e.g., it panicked, with the message.
+    Trap(String),
+}
+
+/// The error when a plugin call traps, or exceeds its budget or resource limits.
+///
+/// The state of the plugin instance may be poisoned after such an error,
+/// so the backends instantiate it again in a new store before the next call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginError {
+    /// The name of the plugin.
+    pub plugin: String,
+    /// The name of the export called.
+    pub export: String,
+    /// The arguments of the call.
+    /// They are empty unless filled by the caller.
+    pub args: Vec<String>,
+    /// The kind of the error.
+    pub kind: PluginErrorKind,
+}
+
+impl PluginError {
+    /// Creates a [`PluginError`].
+    pub fn new(
+        plugin: impl Into<String>,
+        export: impl Into<String>,
+        kind: PluginErrorKind,
+    ) -> Self {
+        Self {
+            plugin: plugin.into(),
+            export: export.into(),
+            args: vec![],
+            kind,
+        }
+    }
+
+    /// Determine if the error is caused by a trap of the plugin.
+    pub fn is_trap(&self) -> bool {
+        matches!(self.kind, PluginErrorKind::Trap(_))
+    }
+
+    /// Determine if the error is caused by the execution budget or resource limits.
+    pub fn is_budget(&self) -> bool {
+        matches!(
+            self.kind,
+            PluginErrorKind::OutOfFuel
+                | PluginErrorKind::Timeout
+                | PluginErrorKind::MemoryLimit
+                | PluginErrorKind::TableLimit
+        )
+    }
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.kind {
+            PluginErrorKind::OutOfFuel => "ran out of fuel",
+            PluginErrorKind::Timeout => "timed out",
+            PluginErrorKind::MemoryLimit => "exceeded the memory limit",
+            PluginErrorKind::TableLimit => "exceeded the table limit",
+            PluginErrorKind::Trap(_) => "trapped",
+        };
+        write!(
+            f,
+            "Plugin \"{}\" {} when calling `{}`",
+            self.plugin, reason, self.export
+        )?;
+        if !self.args.is_empty() {
+            write!(f, " with {:?}", self.args)?;
+        }
+        if let PluginErrorKind::Trap(message) = &self.kind {
+            write!(f, ": {message}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+/// Gets the range of `len` bytes from `start` in the memory of a plugin.
+///
+/// The pointers are 32-bit in the plugins.
+/// The range is not checked against the memory,
+/// so the backends should get the slice with `get`,
+/// and fail if it is out of bounds.
+pub fn mem_range(start: i32, len: i32) -> Option<Range<usize>> {
+    let start = start as u32 as usize;
+    let len = len as u32 as usize;
+    Some(start..start.checked_add(len)?)
+}
+
 /// Represents a raw plugin module.
 pub trait RawModule: Sized {
     /// The linker type that can create raw module.
@@ -34,6 +234,8 @@ pub trait RawModule: Sized {
     /// Calls a method by name.
     ///
     /// The args and returns are bytes.
+    /// A trap during the call is reported as [`PluginError`],
+    /// and the instance is re-instantiated before the next call.
     fn call<T>(&self, name: &str, args: &[u8], f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T>;
 }
 
@@ -73,12 +275,29 @@ pub trait Linker<M: RawModule>: Sized {
     /// Create the linker.
     fn new(config: Self::Config) -> Result<Self>;
 
+    /// Start preparing a module from binary, e.g., compiling it in the background,
+    /// so that the modules could be prepared in parallel before [`Linker::create`].
+    ///
+    /// The default implementation does nothing.
+    fn prepare(&self, _binary: &[u8]) {}
+
     /// Create a raw module from binary.
-    fn create(&self, binary: &[u8]) -> Result<M>;
+    ///
+    /// The name is used to report [`PluginError`].
+    fn create(&self, name: &str, binary: &[u8]) -> Result<M>;
+
+    /// Set the resource limits of the modules created later,
+    /// overriding the ones in the config.
+    fn set_limits(&mut self, limits: ModuleLimits);
 
     /// Import functions by namespace and names.
     fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, M::Func>) -> Result<()>;
 
+    /// Remove all imported functions,
+    /// so that the modules created later could import different ones.
+    /// The modules created before are not affected.
+    fn clear_imports(&mut self);
+
     /// Wrap a function with args in bytes.
     fn wrap_raw(
         &self,
@@ -91,7 +310,7 @@ pub trait Linker<M: RawModule>: Sized {
         f: impl (Fn(P) -> Result<R>) + Send + Sync + 'static,
     ) -> M::Func {
         self.wrap_raw(move |handle, start, len| {
-            let data = handle.slice(start, len, |data| rmp_serde::from_slice(data))?;
+            let data = handle.slice(start, len, |data| rmp_serde::from_slice(data))??;
             let data = f(data)?;
             let data = rmp_serde::to_vec(&data)?;
             Ok(data)
@@ -104,7 +323,7 @@ pub trait Linker<M: RawModule>: Sized {
         f: impl (Fn(M::LinkerHandle<'_>, P) -> Result<R>) + Send + Sync + 'static,
     ) -> M::Func {
         self.wrap_raw(move |handle, start, len| {
-            let data = handle.slice(start, len, |data| rmp_serde::from_slice(data))?;
+            let data = handle.slice(start, len, |data| rmp_serde::from_slice(data))??;
             let data = f(handle, data)?;
             let data = rmp_serde::to_vec(&data)?;
             Ok(data)
@@ -118,6 +337,9 @@ pub trait Linker<M: RawModule>: Sized {
 /// A handle provides methods to do so.
 pub trait LinkerHandle<'a, M: RawModule> {
     /// Call methods of a module.
+    ///
+    /// It fails if the module is already running,
+    /// e.g., a plugin calls itself through the host.
     fn call<T>(
         &mut self,
         m: &M,
@@ -127,8 +349,12 @@ pub trait LinkerHandle<'a, M: RawModule> {
     ) -> Result<T>;
 
     /// Get memory slice.
-    fn slice<T>(&self, start: i32, len: i32, f: impl FnOnce(&[u8]) -> T) -> T;
+    ///
+    /// It fails if the range is out of the memory.
+    fn slice<T>(&self, start: i32, len: i32, f: impl FnOnce(&[u8]) -> T) -> Result<T>;
 
     /// Get memory mutable slice.
-    fn slice_mut<T>(&mut self, start: i32, len: i32, f: impl FnOnce(&mut [u8]) -> T) -> T;
+    ///
+    /// It fails if the range is out of the memory.
+    fn slice_mut<T>(&mut self, start: i32, len: i32, f: impl FnOnce(&mut [u8]) -> T) -> Result<T>;
 }
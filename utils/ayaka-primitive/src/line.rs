@@ -5,7 +5,7 @@ use std::collections::HashMap;
 
 /// Represents a line in a prograph.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
-#[serde(untagged)]
+#[serde(from = "LineRepr")]
 pub enum Line {
     /// An empty line, usually fallbacks to the base language one.
     Empty,
@@ -14,13 +14,164 @@ pub enum Line {
     /// Some `switches`.
     Switch {
         /// The switch items.
-        switches: Vec<String>,
+        switches: Vec<SwitchItem>,
+        /// The timeout in milliseconds.
+        /// The default item is chosen after the timeout.
+        timeout: Option<u64>,
+        /// The index of the default item.
+        default: Option<usize>,
+    },
+    /// Call a subroutine paragraph.
+    /// The game returns to the next line after the called paragraphs meet the end.
+    Call {
+        /// The tag of the called paragraph.
+        call: Text,
     },
     /// Custom line types.
+    Custom(HashMap<String, RawValue>),
+    /// A line with condition.
+    /// The line is skipped if the condition is false.
+    Cond {
+        /// The condition expression.
+        cond: String,
+        /// The inner line.
+        line: Box<Line>,
+    },
+}
+
+impl Line {
+    /// The inner line without condition.
+    pub fn inner(&self) -> &Line {
+        match self {
+            Self::Cond { line, .. } => line.inner(),
+            _ => self,
+        }
+    }
+
+    /// The condition expression of the line.
+    pub fn condition(&self) -> Option<&str> {
+        match self {
+            Self::Cond { cond, .. } => Some(cond),
+            _ => None,
+        }
+    }
+}
+
+/// An item in `switches`.
+///
+/// It could be deserialized from a string,
+/// or a map with `text`, `if`, `hidden_if` and `goto`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "SwitchItemRepr")]
+pub struct SwitchItem {
+    /// The text of the item.
+    pub text: String,
+    /// The condition expression.
+    /// The item is disabled if it is false.
+    pub cond: Option<String>,
+    /// The condition expression.
+    /// The item is hidden if it is true.
+    pub hidden_if: Option<String>,
+    /// The paragraph to go after the item is chosen.
+    pub goto: Option<Text>,
+}
+
+impl SwitchItem {
+    /// Create a [`SwitchItem`] with only text.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SwitchItemRepr {
+    Text(RawValue),
+    Map {
+        text: RawValue,
+        #[serde(default, rename = "if")]
+        cond: Option<RawValue>,
+        #[serde(default)]
+        hidden_if: Option<RawValue>,
+        #[serde(default)]
+        goto: Option<Text>,
+    },
+}
+
+impl From<SwitchItemRepr> for SwitchItem {
+    fn from(repr: SwitchItemRepr) -> Self {
+        match repr {
+            SwitchItemRepr::Text(text) => Self::new(text.get_str()),
+            SwitchItemRepr::Map {
+                text,
+                cond,
+                hidden_if,
+                goto,
+            } => Self {
+                text: text.get_str().into_owned(),
+                cond: cond.map(|cond| cond.get_str().into_owned()),
+                hidden_if: hidden_if.map(|cond| cond.get_str().into_owned()),
+                goto,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LineRepr {
+    Empty,
+    Text(Text),
+    Cond {
+        #[serde(rename = "if")]
+        cond: RawValue,
+        #[serde(flatten)]
+        line: Box<LineRepr>,
+    },
+    TextMap {
+        text: Text,
+    },
+    Switch {
+        switches: Vec<SwitchItem>,
+        #[serde(default)]
+        timeout: Option<u64>,
+        #[serde(default)]
+        default: Option<usize>,
+    },
+    Call {
+        call: Text,
+    },
     #[serde(with = "maps_duplicate_key_is_error")]
     Custom(HashMap<String, RawValue>),
 }
 
+impl From<LineRepr> for Line {
+    fn from(repr: LineRepr) -> Self {
+        match repr {
+            LineRepr::Empty => Self::Empty,
+            LineRepr::Text(text) | LineRepr::TextMap { text } => Self::Text(text),
+            LineRepr::Cond { cond, line } => Self::Cond {
+                cond: cond.get_str().into_owned(),
+                line: Box::new((*line).into()),
+            },
+            LineRepr::Switch {
+                switches,
+                timeout,
+                default,
+            } => Self::Switch {
+                switches,
+                timeout,
+                default,
+            },
+            LineRepr::Call { call } => Self::Call { call },
+            LineRepr::Custom(props) => Self::Custom(props),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
@@ -39,9 +190,22 @@ mod test {
   - b
 - video: 0
 -
+- call: shop
+- text: abc
+  if: $a > 1
+- exec: $b = 1
+  if: $a
+- switches:
+  - a
+  - text: b
+    if: $a
+    hidden_if: $b
+    goto: para
+  timeout: 3000
+  default: 0
         "#;
         let lines: Vec<Line> = serde_yaml::from_str(lines).unwrap();
-        assert_eq!(lines.len(), 5);
+        assert_eq!(lines.len(), 9);
         assert_eq!(lines[0], Line::Text(text(vec![str("abc")])));
         assert_eq!(
             lines[1],
@@ -53,7 +217,9 @@ mod test {
         assert_eq!(
             lines[2],
             Line::Switch {
-                switches: vec!["a".to_string(), "b".to_string()]
+                switches: vec![SwitchItem::new("a"), SwitchItem::new("b")],
+                timeout: None,
+                default: None,
             }
         );
         assert_eq!(
@@ -61,5 +227,42 @@ mod test {
             Line::Custom(HashMap::from([("video".to_string(), RawValue::Num(0))]))
         );
         assert_eq!(lines[4], Line::Empty);
+        assert_eq!(
+            lines[5],
+            Line::Call {
+                call: text(vec![str("shop")])
+            }
+        );
+        assert_eq!(
+            lines[6],
+            Line::Cond {
+                cond: "$a > 1".to_string(),
+                line: Box::new(Line::Text(text(vec![str("abc")])))
+            }
+        );
+        assert_eq!(lines[6].condition(), Some("$a > 1"));
+        assert_eq!(
+            lines[7].inner(),
+            &Line::Custom(HashMap::from([(
+                "exec".to_string(),
+                RawValue::Str("$b = 1".to_string())
+            )]))
+        );
+        assert_eq!(
+            lines[8],
+            Line::Switch {
+                switches: vec![
+                    SwitchItem::new("a"),
+                    SwitchItem {
+                        text: "b".to_string(),
+                        cond: Some("$a".to_string()),
+                        hidden_if: Some("$b".to_string()),
+                        goto: Some(text(vec![str("para")])),
+                    }
+                ],
+                timeout: Some(3000),
+                default: Some(0),
+            }
+        );
     }
 }
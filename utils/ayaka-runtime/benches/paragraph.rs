@@ -0,0 +1,124 @@
+#![feature(test)]
+
+extern crate test;
+
+use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
+use ayaka_runtime::*;
+use std::{fmt::Write as _, io::Write as _};
+use test::Bencher;
+use vfs::{MemoryFS, VfsPath};
+
+// 100 files * 20 paragraphs * 20 lines = 40k lines per locale.
+const FILES: usize = 100;
+const PARAS: usize = 20;
+const LINES: usize = 20;
+
+fn tag(file: usize, para: usize) -> String {
+    // The first paragraph has the same tag as the file name,
+    // so that it could be referenced from other files.
+    if para == 0 {
+        format!("f{file}")
+    } else {
+        format!("f{file}_p{para}")
+    }
+}
+
+fn write_file(path: &VfsPath, content: &str) {
+    path.create_file()
+        .unwrap()
+        .write_all(content.as_bytes())
+        .unwrap();
+}
+
+fn write_paras(root: &VfsPath, loc: &str) {
+    let dir = root.join("paras").unwrap().join(loc).unwrap();
+    dir.create_dir_all().unwrap();
+    for file in 0..FILES {
+        let mut content = String::new();
+        for para in 0..PARAS {
+            writeln!(content, "- tag: {}", tag(file, para)).unwrap();
+            writeln!(content, "  texts:").unwrap();
+            for line in 0..LINES {
+                writeln!(content, "    - {loc} {file} {para} {line}").unwrap();
+            }
+            if para + 1 < PARAS {
+                writeln!(content, "  next: {}", tag(file, para + 1)).unwrap();
+            } else if file + 1 < FILES {
+                writeln!(content, "  next: {}", tag(file + 1, 0)).unwrap();
+            }
+        }
+        write_file(&dir.join(format!("f{file}.yaml")).unwrap(), &content);
+    }
+}
+
+fn open() -> Context<WasmiModule> {
+    let root = VfsPath::from(MemoryFS::new());
+    write_file(
+        &root.join("config.yaml").unwrap(),
+        "title: Large\nbase_lang: en\nparas: paras\nstart: f0\n",
+    );
+    write_paras(&root, "en");
+    write_paras(&root, "zh-Hans");
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
+    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
+        .with_vfs(&[root])
+        .unwrap()
+        .open();
+    let mut context = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(context)
+        .unwrap();
+    context.set_start_context();
+    context
+}
+
+#[bench]
+fn find_para(b: &mut Bencher) {
+    let context = open();
+    let game = context.game();
+    let loc = locale!("en");
+    let base_tag = format!("f{}", FILES - 1);
+    let tag = tag(FILES - 1, PARAS - 1);
+    b.iter(|| game.find_para(&loc, &base_tag, &tag).unwrap().texts.len());
+}
+
+#[bench]
+fn find_para_chain(b: &mut Bencher) {
+    let context = open();
+    let game = context.game();
+    let loc = locale!("zh-CN");
+    let base_tag = format!("f{}", FILES - 1);
+    let tag = tag(FILES - 1, PARAS - 1);
+    b.iter(|| {
+        game.find_para_chain(&loc, &base_tag, &tag)
+            .find_map(|(_, p)| p.and_then(|p| p.texts.last()))
+            .is_some()
+    });
+}
+
+#[bench]
+fn next_run(b: &mut Bencher) {
+    let mut context = open();
+    // Run through a whole file.
+    b.iter(|| {
+        context.set_start_context();
+        (0..PARAS * LINES)
+            .filter_map(|_| context.next_run())
+            .count()
+    });
+}
+
+#[bench]
+fn get_action(b: &mut Bencher) {
+    let mut context = open();
+    let loc = locale!("zh-CN");
+    let raw_ctxs = (0..PARAS * LINES)
+        .filter_map(|_| context.next_run())
+        .collect::<Vec<_>>();
+    b.iter(|| {
+        raw_ctxs.iter().for_each(|raw_ctx| {
+            std::hint::black_box(context.get_action(&loc, raw_ctx).unwrap());
+        })
+    });
+}
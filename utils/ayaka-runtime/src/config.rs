@@ -1,7 +1,10 @@
 use crate::*;
-use fallback::Fallback;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+};
+use vfs::VfsPath;
 
 /// The paragraph in a paragraph config.
 #[derive(Debug, Deserialize)]
@@ -20,6 +23,37 @@ pub struct Paragraph {
     pub next: Option<Text>,
 }
 
+/// The paragraphs in a paragraph config, indexed by tags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "Vec<Paragraph>")]
+pub struct ParagraphFile {
+    paras: Vec<Paragraph>,
+    tags: HashMap<String, usize>,
+}
+
+impl ParagraphFile {
+    /// Find a paragraph by tag.
+    pub fn get(&self, tag: &str) -> Option<&Paragraph> {
+        self.tags.get(tag).map(|&i| &self.paras[i])
+    }
+
+    /// The paragraphs in the order of the file.
+    pub fn paras(&self) -> &[Paragraph] {
+        &self.paras
+    }
+}
+
+impl From<Vec<Paragraph>> for ParagraphFile {
+    fn from(paras: Vec<Paragraph>) -> Self {
+        let mut tags = HashMap::with_capacity(paras.len());
+        for (i, p) in paras.iter().enumerate() {
+            // The first paragraph wins if the tags are duplicated.
+            tags.entry(p.tag.clone()).or_insert(i);
+        }
+        Self { paras, tags }
+    }
+}
+
 /// The Ayaka config.
 /// It should be deserialized from a YAML file.
 #[derive(Debug, Default, Deserialize)]
@@ -45,16 +79,344 @@ pub struct GameConfig {
     /// If the runtime fails to choose a best match,
     /// it fallbacks to this one.
     pub base_lang: Locale,
+    /// The fallback chains of the locales.
+    /// The base language is always the last one of a chain.
+    ///
+    /// If a locale is not specified, the parents are derived by
+    /// removing the variants, region and script of it.
+    /// The parents are followed recursively.
+    #[serde(default)]
+    pub fallback: HashMap<Locale, Vec<Locale>>,
 }
 
 /// The plugin config.
+///
+/// The unknown fields are rejected,
+/// e.g., the per-plugin settings misplaced out of [`ModuleConfig`].
 #[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PluginConfig {
     /// The directory of the plugins.
     pub dir: String,
-    /// The names of the plugins, without extension.
+    /// The plugins, with names without extension.
+    #[serde(default)]
+    pub modules: Vec<ModuleConfig>,
+    /// The order of pipelines.
+    #[serde(default)]
+    pub pipeline: PipelineConfig,
+}
+
+impl PluginConfig {
+    /// Get the settings of a plugin.
+    /// They are the default ones if not specified.
+    pub fn module(&self, name: &str) -> ModuleConfig {
+        self.modules
+            .iter()
+            .find(|m| m.name == name)
+            .cloned()
+            .unwrap_or_else(|| ModuleConfig::from(name))
+    }
+}
+
+/// A plugin in [`PluginConfig::modules`], with all its settings.
+///
+/// It could be a name, or a map with the name and the settings:
+/// ```yaml
+/// modules:
+///   - ayacript
+///   - name: media
+///     config:
+///       bgs: bg
+///     memory: 16777216
+///     capabilities:
+///       plugin: false
+///     on_trap: skip
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawModuleConfig")]
+pub struct ModuleConfig {
+    /// The name of the plugin, without extension.
+    pub name: String,
+    /// The config passed to the plugin.
+    pub config: VarMap,
+    /// The resource limits of the plugin.
+    pub limits: ModuleLimits,
+    /// The capabilities of the plugin.
+    pub capabilities: PluginCapabilities,
+    /// The policy when the plugin traps.
+    pub on_trap: TrapPolicy,
+}
+
+impl From<&str> for ModuleConfig {
+    fn from(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawModuleConfig {
+    Name(String),
+    Full {
+        name: String,
+        #[serde(default)]
+        config: VarMap,
+        #[serde(default)]
+        memory: Option<usize>,
+        #[serde(default)]
+        table: Option<usize>,
+        #[serde(default)]
+        capabilities: PluginCapabilities,
+        #[serde(default)]
+        on_trap: TrapPolicy,
+    },
+}
+
+impl From<RawModuleConfig> for ModuleConfig {
+    fn from(value: RawModuleConfig) -> Self {
+        match value {
+            RawModuleConfig::Name(name) => Self::from(name.as_str()),
+            RawModuleConfig::Full {
+                name,
+                config,
+                memory,
+                table,
+                capabilities,
+                on_trap,
+            } => Self {
+                name,
+                config,
+                limits: ModuleLimits { memory, table },
+                capabilities,
+                on_trap,
+            },
+        }
+    }
+}
+
+/// The capabilities of a plugin.
+///
+/// The imports of a disabled capability are not linked,
+/// and a plugin requiring them fails to load.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PluginCapabilities {
+    /// Whether the `fs` imports are linked.
+    pub fs: bool,
+    /// The directories relative to the root that the plugin could read.
+    /// If it is [`None`], the whole root could be read.
+    pub dirs: Option<Vec<String>>,
+    /// Whether the plugin could write to its data directory.
+    /// It takes effect only if `fs` is enabled.
+    pub data: bool,
+    /// Whether the `plugin` imports are linked,
+    /// which call the methods of other plugins.
+    pub plugin: bool,
+    /// Whether the `rand` imports are linked.
+    pub rand: bool,
+    /// Whether the `script` imports are linked.
+    pub script: bool,
+}
+
+impl Default for PluginCapabilities {
+    fn default() -> Self {
+        Self {
+            fs: true,
+            dirs: None,
+            data: true,
+            plugin: true,
+            rand: true,
+            script: true,
+        }
+    }
+}
+
+/// What happens when a call into a plugin traps,
+/// or exceeds its execution budget.
+///
+/// The trapped instance is re-instantiated in all cases,
+/// and a plugin exceeding its budget is always disabled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrapPolicy {
+    /// Return the error to the caller.
+    ///
+    /// When stepping to the next line, the run stops,
+    /// and the error is recorded as a [`Diagnostic`].
+    #[default]
+    Fail,
+    /// Skip the result of the call, e.g., the text command or the line.
+    Skip,
+    /// Skip the result of the call, and disable the plugin.
+    Disable,
+}
+
+/// The order of the action and game plugins.
+///
+/// The specified plugins run first, in the specified order,
+/// and the others are sorted by [`PluginType::priority`].
+#[derive(Debug, Default, Deserialize)]
+pub struct PipelineConfig {
+    /// The names of action plugins.
+    #[serde(default)]
+    pub action: Vec<String>,
+    /// The names of game plugins.
     #[serde(default)]
-    pub modules: Vec<String>,
+    pub game: Vec<String>,
+}
+
+/// The parent locales derived by removing subtags.
+fn parent_locales(loc: &Locale) -> Vec<Locale> {
+    let mut id = loc.0.clone();
+    let mut parents = vec![];
+    if !id.variants.is_empty() {
+        id.variants = Default::default();
+        parents.push(Locale(id.clone()));
+    }
+    if id.region.is_some() {
+        id.region = None;
+        parents.push(Locale(id.clone()));
+    }
+    if id.script.is_some() {
+        id.script = None;
+        parents.push(Locale(id));
+    }
+    parents
+}
+
+/// The cache of the best matches and the fallback chains of locales.
+#[derive(Debug)]
+struct LocaleCache {
+    locales: Vec<Locale>,
+    base_lang: Locale,
+    /// The fallback chains of the available locales.
+    chains: Vec<Vec<Option<usize>>>,
+    /// The chain if no locale matches.
+    base_chain: Vec<Option<usize>>,
+    matches: RwLock<HashMap<Locale, Option<usize>>>,
+}
+
+impl LocaleCache {
+    fn new<'a>(locales: impl IntoIterator<Item = &'a Locale>, config: &GameConfig) -> Self {
+        let locales = locales.into_iter().cloned().collect::<Vec<_>>();
+        let base = Self::choose_index(&locales, &config.base_lang);
+        let chains = (0..locales.len())
+            .map(|i| {
+                if Some(i) == base {
+                    return vec![base];
+                }
+                let mut chain = vec![Some(i)];
+                Self::extend_chain(&locales, config, i, &mut chain);
+                // The base language is always the last one.
+                chain.retain(|&l| l != base);
+                chain.push(base);
+                chain
+            })
+            .collect();
+        Self {
+            locales,
+            base_lang: config.base_lang.clone(),
+            chains,
+            base_chain: vec![base],
+            matches: RwLock::default(),
+        }
+    }
+
+    /// Append the parents of `locales[i]` recursively.
+    fn extend_chain(
+        locales: &[Locale],
+        config: &GameConfig,
+        i: usize,
+        chain: &mut Vec<Option<usize>>,
+    ) {
+        let parents = match config.fallback.get(&locales[i]) {
+            Some(parents) => parents.clone(),
+            None => parent_locales(&locales[i]),
+        };
+        for parent in parents {
+            if let Some(p) = locales.iter().position(|l| *l == parent) {
+                if !chain.contains(&Some(p)) {
+                    chain.push(Some(p));
+                    Self::extend_chain(locales, config, p, chain);
+                }
+            }
+        }
+    }
+
+    fn choose_index(locales: &[Locale], loc: &Locale) -> Option<usize> {
+        loc.choose_from(locales)
+            .and_then(|chosen| locales.iter().position(|l| l == chosen))
+    }
+
+    fn get(&self, index: Option<usize>) -> &Locale {
+        index.map(|i| &self.locales[i]).unwrap_or(&self.base_lang)
+    }
+
+    /// Choose the best match of `loc`.
+    fn choose(&self, loc: &Locale) -> Option<usize> {
+        if let Some(&index) = self.matches.read().unwrap().get(loc) {
+            return index;
+        }
+        let index = Self::choose_index(&self.locales, loc);
+        self.matches.write().unwrap().insert(loc.clone(), index);
+        index
+    }
+
+    /// The fallback chain of the best match of `loc`.
+    fn chain(&self, loc: &Locale) -> impl Iterator<Item = &Locale> + '_ {
+        let chain = match self.choose(loc) {
+            Some(i) => &self.chains[i],
+            None => &self.base_chain,
+        };
+        chain.iter().map(|&i| self.get(i))
+    }
+}
+
+/// A paragraph file, which may be parsed on demand.
+pub(crate) struct ParagraphEntry {
+    path: Option<VfsPath>,
+    file: OnceLock<anyhow::Result<ParagraphFile>>,
+}
+
+impl ParagraphEntry {
+    pub(crate) fn loaded(file: ParagraphFile) -> Self {
+        Self {
+            path: None,
+            file: OnceLock::from(Ok(file)),
+        }
+    }
+
+    pub(crate) fn lazy(path: VfsPath) -> Self {
+        Self {
+            path: Some(path),
+            file: OnceLock::new(),
+        }
+    }
+
+    /// Parse the file if not parsed yet.
+    /// The error is kept, and returned on every call.
+    fn get(&self) -> Result<&ParagraphFile, &anyhow::Error> {
+        self.file
+            .get_or_init(|| {
+                let path = self
+                    .path
+                    .as_ref()
+                    .expect("lazy paragraph file should have a path");
+                log::debug!("Loading paragraph file {}", path.as_str());
+                path.open_file()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|file| Ok(serde_yaml::from_reader(file)?))
+                    .map_err(|e| {
+                        let e = e.context(format!("Cannot load paragraph file {}", path.as_str()));
+                        log::error!("{e:#}");
+                        e
+                    })
+            })
+            .as_ref()
+    }
 }
 
 /// The full Ayaka game.
@@ -64,12 +426,52 @@ pub struct Game {
     pub config: GameConfig,
     /// The paragraphs, indexed by locale.
     /// The inner is the paragraphs indexed by file names.
-    pub paras: HashMap<Locale, HashMap<String, Vec<Paragraph>>>,
+    paras: HashMap<Locale, HashMap<String, ParagraphEntry>>,
     /// The resources, indexed by locale.
-    pub res: HashMap<Locale, VarMap>,
+    res: HashMap<Locale, VarMap>,
+    para_locales: LocaleCache,
+    res_locales: LocaleCache,
 }
 
 impl Game {
+    /// Create a [`Game`] with config, paragraphs and resources.
+    ///
+    /// The paragraphs are indexed by locale, file name and tag.
+    /// The best matches of the locales are cached.
+    pub fn new(
+        config: GameConfig,
+        paras: HashMap<Locale, HashMap<String, ParagraphFile>>,
+        res: HashMap<Locale, VarMap>,
+    ) -> Self {
+        let paras = paras
+            .into_iter()
+            .map(|(loc, files)| {
+                let files = files
+                    .into_iter()
+                    .map(|(name, file)| (name, ParagraphEntry::loaded(file)))
+                    .collect();
+                (loc, files)
+            })
+            .collect();
+        Self::with_entries(config, paras, res)
+    }
+
+    pub(crate) fn with_entries(
+        config: GameConfig,
+        paras: HashMap<Locale, HashMap<String, ParagraphEntry>>,
+        res: HashMap<Locale, VarMap>,
+    ) -> Self {
+        let para_locales = LocaleCache::new(paras.keys(), &config);
+        let res_locales = LocaleCache::new(res.keys(), &config);
+        Self {
+            config,
+            paras,
+            res,
+            para_locales,
+            res_locales,
+        }
+    }
+
     /// Create a [`RawContext`] at the start of the game.
     pub fn start_context(&self) -> RawContext {
         RawContext {
@@ -79,59 +481,216 @@ impl Game {
         }
     }
 
-    fn choose_from_keys<'a, V>(&'a self, loc: &Locale, map: &'a HashMap<Locale, V>) -> &'a Locale {
-        loc.choose_from(map.keys())
-            .unwrap_or(&self.config.base_lang)
+    /// The locales of the paragraphs.
+    pub fn locales(&self) -> impl Iterator<Item = &Locale> {
+        self.paras.keys()
+    }
+
+    /// The paragraph files with specified locale, indexed by file names.
+    ///
+    /// The files that cannot be loaded are skipped,
+    /// and the errors are returned by [`Game::para_file_error`].
+    pub fn para_files(&self, loc: &Locale) -> impl Iterator<Item = (&String, &ParagraphFile)> {
+        self.paras
+            .get(loc)
+            .into_iter()
+            .flatten()
+            .filter_map(|(name, entry)| entry.get().ok().map(|file| (name, file)))
     }
 
     /// Find a paragraph by tag, with specified locale.
+    ///
+    /// A file that cannot be loaded contains no paragraphs.
     pub fn find_para(&self, loc: &Locale, base_tag: &str, tag: &str) -> Option<&Paragraph> {
-        if let Some(paras) = self.paras.get(loc) {
-            if let Some(paras) = paras.get(base_tag) {
-                for p in paras.iter() {
-                    if p.tag == tag {
-                        return Some(p);
-                    }
-                }
-            }
-        }
-        None
+        self.paras
+            .get(loc)
+            .and_then(|paras| paras.get(base_tag))
+            .and_then(|entry| entry.get().ok())
+            .and_then(|file| file.get(tag))
     }
 
-    /// Find a paragraph by tag, with specified locale.
-    pub fn find_para_fallback(
-        &self,
+    /// The error of loading a paragraph file lazily, with specified locale.
+    pub fn para_file_error(&self, loc: &Locale, base_tag: &str) -> Option<&anyhow::Error> {
+        self.paras
+            .get(loc)
+            .and_then(|paras| paras.get(base_tag))
+            .and_then(|entry| entry.get().err())
+    }
+
+    /// The fallback chain of the paragraphs with specified locale.
+    ///
+    /// The first one is the best match of the locale,
+    /// and the last one is the base language.
+    pub fn locale_chain(&self, loc: &Locale) -> impl Iterator<Item = &Locale> + '_ {
+        self.para_locales.chain(loc)
+    }
+
+    /// Find a paragraph by tag, along the fallback chain of specified locale.
+    ///
+    /// Every level of the chain is yielded, even if the paragraph is missing.
+    pub fn find_para_chain<'a>(
+        &'a self,
         loc: &Locale,
-        base_tag: &str,
-        tag: &str,
-    ) -> Fallback<&Paragraph> {
-        let key = self.choose_from_keys(loc, &self.paras);
-        let base_key = self.choose_from_keys(&self.config.base_lang, &self.paras);
-        Fallback::new(
-            if key == base_key {
-                None
-            } else {
-                self.find_para(key, base_tag, tag)
+        base_tag: &'a str,
+        tag: &'a str,
+    ) -> impl Iterator<Item = (&'a Locale, Option<&'a Paragraph>)> + 'a {
+        self.locale_chain(loc)
+            .map(move |loc| (loc, self.find_para(loc, base_tag, tag)))
+    }
+
+    /// Find the resource maps along the fallback chain of specified locale.
+    pub fn find_res_chain(&self, loc: &Locale) -> impl Iterator<Item = &VarMap> + '_ {
+        self.res_locales
+            .chain(loc)
+            .filter_map(|loc| self.res.get(loc))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::collections::HashMap;
+
+    fn paras(lines: usize) -> ParagraphFile {
+        let texts = "    - text\n".repeat(lines);
+        serde_yaml::from_str(&format!(
+            "- tag: a\n  texts:\n{texts}- tag: a\n  texts:\n    - duplicate\n"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn find_para() {
+        let game = Game::new(
+            GameConfig {
+                base_lang: locale!("en"),
+                ..Default::default()
             },
-            self.find_para(base_key, base_tag, tag),
+            HashMap::from([
+                (locale!("en"), HashMap::from([("a".to_string(), paras(2))])),
+                (
+                    locale!("zh-Hans"),
+                    HashMap::from([("a".to_string(), paras(3))]),
+                ),
+            ]),
+            HashMap::default(),
+        );
+        let lines = |loc: Locale| {
+            game.find_para_chain(&loc, "a", "a")
+                .find_map(|(_, p)| p)
+                .unwrap()
+                .texts
+                .len()
+        };
+        // The first paragraph wins.
+        assert_eq!(lines(locale!("en")), 2);
+        assert_eq!(lines(locale!("zh-CN")), 3);
+        // Cached.
+        assert_eq!(lines(locale!("zh-CN")), 3);
+        assert_eq!(lines(locale!("ja")), 2);
+        assert!(game.find_para(&locale!("en"), "a", "b").is_none());
+    }
+
+    #[test]
+    fn locale_chain() {
+        let files = || HashMap::from([("a".to_string(), paras(1))]);
+        let game = Game::new(
+            GameConfig {
+                base_lang: locale!("ja"),
+                fallback: HashMap::from([(locale!("zh-Hant"), vec![locale!("zh-Hans")])]),
+                ..Default::default()
+            },
+            HashMap::from([
+                (locale!("ja"), files()),
+                (locale!("zh-Hans"), files()),
+                (locale!("zh-Hant"), files()),
+                (locale!("zh-Hant-HK"), files()),
+            ]),
+            HashMap::default(),
+        );
+        let chain = |loc: Locale| game.locale_chain(&loc).cloned().collect::<Vec<_>>();
+        assert_eq!(
+            chain(locale!("zh-Hant")),
+            [locale!("zh-Hant"), locale!("zh-Hans"), locale!("ja")]
+        );
+        // Derived from the parent locale.
+        assert_eq!(
+            chain(locale!("zh-Hant-HK")),
+            [
+                locale!("zh-Hant-HK"),
+                locale!("zh-Hant"),
+                locale!("zh-Hans"),
+                locale!("ja")
+            ]
+        );
+        assert_eq!(chain(locale!("ja")), [locale!("ja")]);
+    }
+
+    #[test]
+    fn module_config() {
+        let config: PluginConfig = serde_yaml::from_str(
+            "dir: plugins\nmodules:\n  - ayacript\n  - name: media\n    config:\n      bgs: bg\n      fade: 500\n    memory: 16777216\n",
         )
+        .unwrap();
+        assert_eq!(
+            config.modules,
+            [
+                ModuleConfig::from("ayacript"),
+                ModuleConfig {
+                    name: "media".to_string(),
+                    config: VarMap::from([
+                        ("bgs".to_string(), RawValue::Str("bg".to_string())),
+                        ("fade".to_string(), RawValue::Num(500)),
+                    ]),
+                    limits: ModuleLimits {
+                        memory: Some(16777216),
+                        table: None,
+                    },
+                    ..Default::default()
+                },
+            ]
+        );
+        assert!(config.module("ayacript").config.is_empty());
+        assert_eq!(config.module("media").config.len(), 2);
+        assert_eq!(config.module("ayacript").limits, ModuleLimits::default());
+        assert_eq!(config.module("media").limits.memory, Some(16777216));
+        assert_eq!(config.module("unknown"), ModuleConfig::from("unknown"));
     }
 
-    fn find_res(&self, loc: &Locale) -> Option<&VarMap> {
-        self.res.get(loc)
+    #[test]
+    fn module_policy() {
+        let config: PluginConfig = serde_yaml::from_str(
+            "dir: plugins
+modules:
+  - name: basictex
+    on_trap: skip
+  - name: media
+    on_trap: disable
+    capabilities:
+      fs: false
+  - ayacript
+",
+        )
+        .unwrap();
+        assert_eq!(config.module("basictex").on_trap, TrapPolicy::Skip);
+        assert_eq!(config.module("media").on_trap, TrapPolicy::Disable);
+        assert_eq!(config.module("ayacript").on_trap, TrapPolicy::Fail);
+        assert!(!config.module("media").capabilities.fs);
+        assert_eq!(
+            config.module("ayacript").capabilities,
+            PluginCapabilities::default()
+        );
     }
 
-    /// Find the resource map with specified locale.
-    pub fn find_res_fallback(&self, loc: &Locale) -> Fallback<&VarMap> {
-        let key = self.choose_from_keys(loc, &self.res);
-        let base_key = self.choose_from_keys(&self.config.base_lang, &self.res);
-        Fallback::new(
-            if key == base_key {
-                None
-            } else {
-                self.find_res(key)
-            },
-            self.find_res(base_key),
+    #[test]
+    fn misplaced_policy() {
+        // The per-plugin settings should be in the modules.
+        assert!(serde_yaml::from_str::<PluginConfig>(
+            "dir: plugins
+on_trap:
+  basictex: skip
+",
         )
+        .is_err());
     }
 }
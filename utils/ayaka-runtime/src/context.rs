@@ -1,3 +1,10 @@
+mod coverage;
+mod diagnostic;
+mod explore;
+pub use coverage::*;
+pub use diagnostic::*;
+pub use explore::*;
+
 use crate::{
     plugin::{LoadStatus, Runtime},
     *,
@@ -7,21 +14,48 @@ use ayaka_bindings_types::*;
 use fallback::Fallback;
 use log::error;
 use serde::Serialize;
-use std::{borrow::Cow, collections::HashMap, future::Future, path::Path, pin::pin, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    path::Path,
+    pin::pin,
+    sync::{Arc, Mutex},
+};
 use stream_future::{stream, Stream};
 use trylog::macros::*;
 use vfs::*;
 use vfs_tar::TarFS;
 
+/// The max count of the [`Diagnostic`]s kept until taken.
+pub const MAX_DIAGNOSTICS: usize = 256;
+
 /// The game running context.
 pub struct Context<M: RawModule + Send + Sync + 'static> {
     game: Game,
     root_path: VfsPath,
     frontend: FrontendType,
+    options: ContextOptions,
     runtime: Arc<Runtime<M>>,
     ctx: RawContext,
-    switches: Vec<bool>,
+    switches: Vec<SwitchState>,
+    switch_default: Option<usize>,
     vars: VarMap,
+    diagnostics: Mutex<VecDeque<Diagnostic>>,
+    coverage: Coverage,
+}
+
+#[derive(Debug, Clone)]
+struct SwitchState {
+    enabled: bool,
+    hidden: bool,
+    goto: Option<Text>,
+}
+
+impl SwitchState {
+    fn available(&self) -> bool {
+        self.enabled && !self.hidden
+    }
 }
 
 /// The open status when creating [`Context`].
@@ -51,16 +85,65 @@ impl From<LoadStatus> for OpenStatus {
     }
 }
 
+/// The source of an [`Action`] in the fallback chain of locales.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActionSource {
+    /// The level in the fallback chain, start by 0.
+    /// The best match of the locale is level 0.
+    pub level: usize,
+    /// The locale of the level.
+    pub locale: Locale,
+}
+
+/// The options of [`Context`].
+#[derive(Debug, Default, Clone)]
+struct ContextOptions {
+    lazy: bool,
+    strict: bool,
+    seed: Option<u64>,
+}
+
 /// Builder of [`Context`].
 pub struct ContextBuilder<M: RawModule + Send + Sync + 'static> {
     frontend: FrontendType,
     linker: M::Linker,
+    options: ContextOptions,
 }
 
 impl<M: RawModule + Send + Sync + 'static> ContextBuilder<M> {
     /// Create a new [`ContextBuilder`] with frontend type and plugin runtime linker.
     pub fn new(frontend: FrontendType, linker: M::Linker) -> Self {
-        Self { frontend, linker }
+        Self {
+            frontend,
+            linker,
+            options: ContextOptions::default(),
+        }
+    }
+
+    /// Load the paragraph files lazily.
+    ///
+    /// If `true`, a paragraph file is parsed the first time it is needed,
+    /// and the locales never used are not parsed at all.
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.options.lazy = lazy;
+        self
+    }
+
+    /// Treat the [`Diagnostic`]s as errors.
+    ///
+    /// If `true`, [`Context::get_action`] fails when a [`Diagnostic`] is found,
+    /// and [`Context::next_run`] stops.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Use a fixed seed of the random number generator for new games.
+    ///
+    /// By default, a random seed is chosen for every new game.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.options.seed = Some(seed);
+        self
     }
 
     fn open_fs_from_paths(paths: &'_ [impl AsRef<Path>]) -> Result<(VfsPath, Cow<'_, str>)> {
@@ -109,6 +192,7 @@ impl<M: RawModule + Send + Sync + 'static> ContextBuilder<M> {
             filename,
             frontend: self.frontend,
             linker: self.linker,
+            options: self.options,
         })
     }
 
@@ -122,6 +206,7 @@ impl<M: RawModule + Send + Sync + 'static> ContextBuilder<M> {
             filename: "config.yaml".into(),
             frontend: self.frontend,
             linker: self.linker,
+            options: self.options,
         })
     }
 }
@@ -132,12 +217,19 @@ pub struct ContextBuilderWithPaths<'a, M: RawModule + Send + Sync + 'static> {
     filename: Cow<'a, str>,
     frontend: FrontendType,
     linker: M::Linker,
+    options: ContextOptions,
 }
 
 impl<'a, M: RawModule + Send + Sync + 'static> ContextBuilderWithPaths<'a, M> {
     /// Open the config and load the [`Context`].
     pub fn open(self) -> impl Future<Output = Result<Context<M>>> + Stream<Item = OpenStatus> + 'a {
-        Context::<M>::open(self.root_path, self.filename, self.frontend, self.linker)
+        Context::<M>::open(
+            self.root_path,
+            self.filename,
+            self.frontend,
+            self.linker,
+            self.options,
+        )
     }
 }
 
@@ -148,17 +240,13 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         filename: impl AsRef<str> + 'a,
         frontend: FrontendType,
         linker: M::Linker,
+        options: ContextOptions,
     ) -> Result<Self> {
         yield OpenStatus::LoadProfile;
         let file = root_path.join(filename.as_ref())?.open_file()?;
         let mut config: GameConfig = serde_yaml::from_reader(file)?;
         let runtime = {
-            let runtime = Runtime::load(
-                &config.plugins.dir,
-                &root_path,
-                &config.plugins.modules,
-                linker,
-            );
+            let runtime = Runtime::load(&config.plugins, &root_path, linker);
             let mut runtime = pin!(runtime);
             while let Some(load_status) = runtime.next().await {
                 yield load_status.into();
@@ -173,16 +261,24 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         let res = Self::load_resource(&config, &root_path)?;
 
         yield OpenStatus::LoadParagraph;
-        let paras = Self::load_paragraph(&config, &root_path)?;
+        let paras = Self::load_paragraph(&config, &root_path, options.lazy)?;
+        let game = Game::with_entries(config, paras, res);
+        if !options.lazy {
+            Self::check_exec(&game, &runtime)?;
+        }
 
         Ok(Self {
-            game: Game { config, paras, res },
+            game,
             root_path,
             frontend,
+            options,
             runtime,
             ctx: RawContext::default(),
             switches: vec![],
+            switch_default: None,
             vars: VarMap::default(),
+            diagnostics: Mutex::default(),
+            coverage: Coverage::default(),
         })
     }
 
@@ -192,10 +288,34 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                 title: &config.title,
                 author: &config.author,
                 props: &config.props,
+                config: module.config(),
             };
-            let res = module.process_game(ctx)?;
-            for (key, value) in res.props {
-                config.props.insert(key, value);
+            if let Some(res) = module.recover(module.process_game(ctx))? {
+                for (key, value) in res.props {
+                    config.props.insert(key, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a line module provides `exec` if the conditions are used.
+    ///
+    /// The paragraph files loaded lazily are not checked,
+    /// and the conditions are reported as [`Diagnostic`]s when running.
+    fn check_exec(game: &Game, runtime: &Runtime<M>) -> Result<()> {
+        if runtime.line_module("exec").is_some() {
+            return Ok(());
+        }
+        for (base_tag, file) in game.para_files(&game.config.base_lang) {
+            for para in file.paras() {
+                if para.texts.iter().any(has_condition) {
+                    bail!(
+                        "Paragraph \"{}\" in \"{}\" uses conditions, but no plugin provides command exec",
+                        para.tag.escape_default(),
+                        base_tag.escape_default()
+                    );
+                }
             }
         }
         Ok(())
@@ -229,7 +349,8 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
     fn load_paragraph(
         config: &GameConfig,
         root_path: &VfsPath,
-    ) -> Result<HashMap<Locale, HashMap<String, Vec<Paragraph>>>> {
+        lazy: bool,
+    ) -> Result<HashMap<Locale, HashMap<String, ParagraphEntry>>> {
         let mut paras = HashMap::new();
         let paras_path = root_path.join(&config.paras)?;
         for p in paras_path.read_dir()? {
@@ -243,9 +364,13 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                                 .strip_suffix(".yaml")
                                 .unwrap_or_default()
                                 .to_string();
-                            let para = p.open_file()?;
-                            let para = serde_yaml::from_reader(para)?;
-                            paras_map.insert(key, para);
+                            let entry = if lazy {
+                                ParagraphEntry::lazy(p)
+                            } else {
+                                let para = p.open_file()?;
+                                ParagraphEntry::loaded(serde_yaml::from_reader(para)?)
+                            };
+                            paras_map.insert(key, entry);
                         }
                     }
                     paras.insert(loc, paras_map);
@@ -255,24 +380,103 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         Ok(paras)
     }
 
+    /// Reload the paragraphs and resources.
+    ///
+    /// The current [`RawContext`] is kept if the current paragraph
+    /// and the paragraphs to return to in the call stack still exist,
+    /// otherwise it is reset to the start of the game.
+    /// Returns whether the current [`RawContext`] is kept.
+    pub fn reload(&mut self) -> Result<bool> {
+        let res = Self::load_resource(&self.game.config, &self.root_path)?;
+        let paras = Self::load_paragraph(&self.game.config, &self.root_path, self.options.lazy)?;
+        let config = std::mem::take(&mut self.game.config);
+        let game = Game::with_entries(config, paras, res);
+        if !self.options.lazy {
+            if let Err(e) = Self::check_exec(&game, &self.runtime) {
+                // Keep the old paragraphs.
+                self.game.config = game.config;
+                return Err(e);
+            }
+        }
+        self.game = game;
+        let base_lang = &self.game.config.base_lang;
+        let missing = std::iter::once((&self.ctx.cur_base_para, &self.ctx.cur_para))
+            .chain(
+                self.ctx
+                    .call_stack
+                    .iter()
+                    .map(|frame| (&frame.cur_base_para, &frame.cur_para)),
+            )
+            .find(|(base_para, para)| self.game.find_para(base_lang, base_para, para).is_none())
+            .map(|(_, para)| para.clone());
+        if let Some(para) = &missing {
+            log::warn!(
+                "Cannot find paragraph \"{}\" after reloading, restart the game",
+                para.escape_default()
+            );
+            self.set_start_context();
+        }
+        Ok(missing.is_none())
+    }
+
+    /// Reload the plugins with a new linker.
+    ///
+    /// The game plugins are not executed again.
+    /// The data directory is kept.
+    pub async fn reload_plugins(&mut self, linker: M::Linker) -> Result<()> {
+        let runtime = Runtime::load(&self.game.config.plugins, &self.root_path, linker).await?;
+        if !self.options.lazy {
+            Self::check_exec(&self.game, &runtime)?;
+        }
+        runtime.set_rng_state(self.ctx.rng);
+        runtime.set_data_dir(self.runtime.data_dir());
+        self.runtime = runtime;
+        Ok(())
+    }
+
     /// Initialize the [`RawContext`] at the start of the game.
     pub fn set_start_context(&mut self) {
-        self.set_context(self.game().start_context())
+        self.set_context(self.start_context())
+    }
+
+    /// Create a [`RawContext`] at the start of the game,
+    /// with the seed of the random number generator.
+    pub fn start_context(&self) -> RawContext {
+        let mut ctx = self.game.start_context();
+        ctx.rng.seed = self.options.seed.unwrap_or_else(rand::random);
+        ctx
     }
 
     /// Initialize the [`RawContext`] with given record.
+    ///
+    /// The state of the random number generator is restored,
+    /// so that the same numbers are generated.
+    /// The entered paragraph is restored, too,
+    /// so that the paragraph plugins are not notified again.
     pub fn set_context(&mut self, ctx: RawContext) {
+        self.runtime.set_rng_state(ctx.rng);
         self.ctx = ctx;
     }
 
-    fn current_paragraph(&self, loc: &Locale) -> Option<&Paragraph> {
-        self.game
-            .find_para(loc, &self.ctx.cur_base_para, &self.ctx.cur_para)
+    /// Save the states of the state plugins.
+    pub fn plugin_states(&self) -> HashMap<String, Vec<u8>> {
+        self.runtime.save_states()
     }
 
-    fn current_paragraph_fallback(&self, loc: &Locale) -> Fallback<&Paragraph> {
+    /// Restore the states of the state plugins.
+    /// The plugins not in `states` are reset.
+    pub fn set_plugin_states(&self, states: &HashMap<String, Vec<u8>>) {
+        self.runtime.load_states(states)
+    }
+
+    /// Store the state of the random number generator into [`RawContext`].
+    fn sync_rng(&mut self) {
+        self.ctx.rng = self.runtime.rng_state();
+    }
+
+    fn current_paragraph(&self, loc: &Locale) -> Option<&Paragraph> {
         self.game
-            .find_para_fallback(loc, &self.ctx.cur_base_para, &self.ctx.cur_para)
+            .find_para(loc, &self.ctx.cur_base_para, &self.ctx.cur_para)
     }
 
     fn current_text(&self, loc: &Locale) -> Option<&Line> {
@@ -281,9 +485,7 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
     }
 
     fn find_res(&self, loc: &Locale, key: &str) -> Option<&RawValue> {
-        self.game
-            .find_res_fallback(loc)
-            .and_then(|map| map.get(key))
+        self.game.find_res_chain(loc).find_map(|map| map.get(key))
     }
 
     /// The inner [`Game`] object.
@@ -291,31 +493,82 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         &self.game
     }
 
+    /// The plugin runtime.
+    pub fn runtime(&self) -> &Runtime<M> {
+        &self.runtime
+    }
+
     /// The root path of config.
     pub fn root_path(&self) -> &VfsPath {
         &self.root_path
     }
 
+    /// Set the writable data directory of the plugins.
+    ///
+    /// See [`Runtime::set_data_dir`].
+    pub fn set_data_dir(&self, path: Option<VfsPath>) {
+        self.runtime.set_data_dir(path)
+    }
+
     /// Call the part of script with this context.
     pub fn call(&self, text: &Text) -> Result<String> {
         let mut str = String::new();
         for sub_text in &text.sub_texts {
-            let sub_action = self.parse_sub_text(sub_text, None, &self.ctx.locals)?;
+            let sub_action = self.parse_sub_text(sub_text, None, &self.ctx)?;
             str.push_str(&sub_action.to_string());
         }
         Ok(str.trim().to_string())
     }
 
     /// Choose a switch item by index, start by 0.
-    pub fn switch(&mut self, i: usize) {
-        assert!((0..self.switches.len()).contains(&i));
-        assert!(self.switches[i]);
+    ///
+    /// It fails if the item is out of range, hidden or disabled,
+    /// e.g., a stale choice from the frontend.
+    pub fn switch(&mut self, i: usize) -> Result<()> {
+        match self.switches.get(i) {
+            Some(state) if state.available() => {}
+            Some(_) => bail!("The switch {i} is not available"),
+            None => bail!("The switch {i} is out of range"),
+        }
+        let goto = self.switches[i].goto.clone();
         self.ctx
             .locals
             .insert("?".to_string(), RawValue::Num(i as i64));
+        self.ctx
+            .locals
+            .insert("?timeout".to_string(), RawValue::Bool(false));
         for i in 0..self.switches.len() {
             self.ctx.locals.remove(&i.to_string());
         }
+        if let Some(goto) = goto {
+            let tag = self.call(&goto)?;
+            if tag.is_empty() {
+                log::warn!("Empty goto paragraph, ignored");
+            } else {
+                let base_para = self.ctx.cur_base_para.clone();
+                self.jump(base_para, tag, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Choose the default switch item after the timeout of switches.
+    ///
+    /// The chosen index is stored in `$?`, and `$?timeout` is set to `true`.
+    /// Returns [`None`] if there's no available default item.
+    pub fn switch_timeout(&mut self) -> Result<Option<usize>> {
+        let Some(i) = self.switch_default else {
+            return Ok(None);
+        };
+        if !self.switches.get(i).is_some_and(|state| state.available()) {
+            log::warn!("The default switch {i} is not available");
+            return Ok(None);
+        }
+        self.switch(i)?;
+        self.ctx
+            .locals
+            .insert("?timeout".to_string(), RawValue::Bool(true));
+        Ok(Some(i))
     }
 
     fn parse_text(&self, loc: &Locale, text: &Text, ctx: &RawContext) -> Result<ActionText> {
@@ -329,17 +582,80 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             .map(|value| value.get_str().into_owned())
         });
         for sub_text in &text.sub_texts {
-            let mut sub_action = self.parse_sub_text(sub_text, Some(loc), &ctx.locals)?;
+            let mut sub_action = self.parse_sub_text(sub_text, Some(loc), ctx)?;
             action.text.append(&mut sub_action.text);
         }
         Ok(action)
     }
 
+    /// Record a [`Diagnostic`], unless the same one is not taken yet.
+    /// It is returned as an error in strict mode.
+    fn diagnose(
+        &self,
+        kind: DiagnosticKind,
+        ctx: &RawContext,
+        loc: Option<&Locale>,
+        message: impl Into<String>,
+    ) -> Result<()> {
+        let diag = Diagnostic::new(kind, ctx, loc, message);
+        log::warn!("{diag}");
+        self.push_diagnostic(diag.clone());
+        if self.options.strict {
+            Err(diag.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn push_diagnostic(&self, diag: Diagnostic) {
+        let mut diagnostics = self.diagnostics.lock().unwrap();
+        if !diagnostics.contains(&diag) {
+            if diagnostics.len() >= MAX_DIAGNOSTICS {
+                diagnostics.pop_front();
+            }
+            diagnostics.push_back(diag);
+        }
+    }
+
+    /// Stop the run because of an error in [`Self::next_run`].
+    ///
+    /// The error is recorded as a [`Diagnostic`] unless it is one already.
+    fn stop_run(&self, e: anyhow::Error) {
+        if !e.is::<Diagnostic>() {
+            let diag = Diagnostic::new(
+                DiagnosticKind::PluginFailure,
+                &self.ctx,
+                None,
+                format!("{e:#}"),
+            );
+            error!("{diag}");
+            self.push_diagnostic(diag);
+        }
+    }
+
+    /// Take the [`Diagnostic`]s found since last call.
+    ///
+    /// At most [`MAX_DIAGNOSTICS`] latest ones are kept,
+    /// so the frontends should take them regularly.
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.diagnostics.lock().unwrap()).into()
+    }
+
+    /// The lines executed by [`Self::next_run`].
+    pub fn coverage(&self) -> &Coverage {
+        &self.coverage
+    }
+
+    /// Merge the coverage of another session.
+    pub fn merge_coverage(&mut self, other: &Coverage) {
+        self.coverage.merge(other)
+    }
+
     fn parse_sub_text(
         &self,
         sub_text: &SubText,
         loc: Option<&Locale>,
-        locals: &VarMap,
+        ctx: &RawContext,
     ) -> Result<ActionText> {
         let mut action = ActionText::default();
         match sub_text {
@@ -348,33 +664,53 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             SubText::Cmd(cmd, args) => {
                 let mut arg_strings = vec![];
                 for arg in args {
-                    let sub_action = self.parse_sub_text(arg, loc, locals)?;
+                    let sub_action = self.parse_sub_text(arg, loc, ctx)?;
                     arg_strings.push(sub_action.to_string());
                 }
                 match cmd.as_str() {
                     "res" => {
                         if let Some(loc) = loc {
                             if arg_strings.len() != 1 {
-                                log::warn!("Invalid parameter count for `res`: {}", args.len())
+                                self.diagnose(
+                                    DiagnosticKind::InvalidArgCount,
+                                    ctx,
+                                    Some(loc),
+                                    format!("Invalid parameter count for `res`: {}", args.len()),
+                                )?;
                             }
                             if let Some(n) = arg_strings.first() {
                                 if let Some(value) = self.find_res(loc, n) {
                                     action.push_back_block(value.get_str())
                                 } else {
-                                    log::warn!("Cannot find resource {n}");
+                                    self.diagnose(
+                                        DiagnosticKind::MissingResource,
+                                        ctx,
+                                        Some(loc),
+                                        format!("Cannot find resource {n}"),
+                                    )?;
                                 }
                             }
                         }
                     }
                     "var" => {
                         if arg_strings.len() != 1 {
-                            log::warn!("Invalid parameter count for `var`: {}", args.len())
+                            self.diagnose(
+                                DiagnosticKind::InvalidArgCount,
+                                ctx,
+                                loc,
+                                format!("Invalid parameter count for `var`: {}", args.len()),
+                            )?;
                         }
                         if let Some(n) = arg_strings.first() {
-                            if let Some(value) = locals.get(n) {
+                            if let Some(value) = ctx.locals.get(n) {
                                 action.push_back_block(value.get_str())
                             } else {
-                                log::warn!("Cannot find variable {n}")
+                                self.diagnose(
+                                    DiagnosticKind::MissingVariable,
+                                    ctx,
+                                    loc,
+                                    format!("Cannot find variable {n}"),
+                                )?;
                             }
                         }
                     }
@@ -383,10 +719,20 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                             let ctx = TextProcessContextRef {
                                 game_props: &self.game.config.props,
                                 frontend: self.frontend,
+                                config: module.config(),
                             };
-                            let mut res = module.dispatch_text(cmd, &arg_strings, ctx)?;
-                            action.text.append(&mut res.text.text);
-                            action.vars.extend(res.text.vars);
+                            let res = module.dispatch_text(cmd, &arg_strings, ctx);
+                            if let Some(mut res) = module.recover(res)? {
+                                action.text.append(&mut res.text.text);
+                                action.vars.extend(res.text.vars);
+                            }
+                        } else {
+                            self.diagnose(
+                                DiagnosticKind::UnknownCommand,
+                                ctx,
+                                loc,
+                                format!("Cannot find plugin for command `{cmd}`"),
+                            )?;
                         }
                     }
                 }
@@ -395,22 +741,33 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         Ok(action)
     }
 
-    fn parse_switches(&self, s: &[String]) -> Vec<Switch> {
+    fn parse_switches(
+        &self,
+        s: &[SwitchItem],
+        timeout: Option<u64>,
+        default: Option<usize>,
+    ) -> Vec<Switch> {
         s.iter()
             .zip(&self.switches)
-            .map(|(item, enabled)| Switch {
-                text: item.clone(),
-                enabled: *enabled,
+            .enumerate()
+            .map(|(i, (item, state))| Switch {
+                text: item.text.clone(),
+                enabled: state.enabled,
+                hidden: state.hidden,
+                timeout: timeout.filter(|_| default == Some(i)),
             })
             .collect()
     }
 
     fn process_line(&mut self, t: Line) -> Result<()> {
         match t {
-            Line::Empty | Line::Text(_) => {}
-            Line::Switch { switches } => {
+            Line::Empty | Line::Text(_) | Line::Call { .. } => {}
+            Line::Switch {
+                switches, default, ..
+            } => {
                 self.switches.clear();
-                for i in 0..switches.len() {
+                self.switch_default = default;
+                for (i, item) in switches.into_iter().enumerate() {
                     let enabled = self
                         .ctx
                         .locals
@@ -421,7 +778,24 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                     } else {
                         enabled.get_bool()
                     };
-                    self.switches.push(enabled);
+                    let enabled = enabled
+                        && item.cond.as_ref().is_none_or(|cond| {
+                            unwrap_or_default_log!(
+                                self.check_condition(cond),
+                                "Cannot evaluate switch condition"
+                            )
+                        });
+                    let hidden = item.hidden_if.as_ref().is_some_and(|cond| {
+                        unwrap_or_default_log!(
+                            self.check_condition(cond),
+                            "Cannot evaluate switch condition"
+                        )
+                    });
+                    self.switches.push(SwitchState {
+                        enabled,
+                        hidden,
+                        goto: item.goto,
+                    });
                 }
             }
             Line::Custom(props) => {
@@ -434,46 +808,111 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                             frontend: self.frontend,
                             ctx: &self.ctx,
                             props: &props,
+                            config: module.config(),
                         };
-                        let res = module.dispatch_line(cmd, ctx)?;
-                        self.ctx.locals.extend(res.locals);
-                        self.vars.extend(res.vars);
+                        if let Some(res) = module.recover(module.dispatch_line(cmd, ctx))? {
+                            self.ctx.locals.extend(res.locals);
+                            self.vars.extend(res.vars);
+                        }
                     } else {
-                        bail!("Cannot find command {}", cmd)
+                        self.diagnose(
+                            DiagnosticKind::UnknownCommand,
+                            &self.ctx,
+                            None,
+                            format!("Cannot find plugin for command `{cmd}`"),
+                        )?;
                     }
                 }
             }
+            Line::Cond { line, .. } => self.process_line(*line)?,
         }
         Ok(())
     }
 
-    fn merge_action(&self, action: Fallback<Action>) -> Result<Action> {
-        match action.unzip() {
-            (None, None) => Ok(Action::default()),
-            (Some(action), None) | (None, Some(action)) => Ok(action),
-            (Some(action), Some(action_base)) => match (action, action_base) {
-                (Action::Text(action), Action::Text(action_base)) => {
-                    let action = Fallback::new(Some(action), Some(action_base));
-                    let action = action.spec();
-                    Ok(Action::Text(ActionText {
-                        text: action.text.and_any().unwrap_or_default(),
-                        ch_key: action.ch_key.flatten().fallback(),
-                        character: action.character.flatten().fallback(),
-                        vars: action.vars.and_any().unwrap_or_default(),
-                    }))
-                }
-                (Action::Switches(mut switches), Action::Switches(switches_base)) => {
-                    for (item, item_base) in switches.iter_mut().zip(switches_base) {
-                        item.enabled = item_base.enabled;
-                    }
-                    Ok(Action::Switches(switches))
+    /// Evaluate the condition expression of a line with the `exec` command.
+    ///
+    /// If the command is missing, or the call is skipped by the [`TrapPolicy`],
+    /// the condition is false and a [`Diagnostic`] is recorded.
+    fn check_condition(&self, cond: &str) -> Result<bool> {
+        let Some(module) = self.runtime.line_module("exec") else {
+            self.diagnose(
+                DiagnosticKind::InvalidCondition,
+                &self.ctx,
+                None,
+                format!("Cannot find command exec to evaluate condition `{cond}`"),
+            )?;
+            return Ok(false);
+        };
+        let props = HashMap::from([(
+            "exec".to_string(),
+            RawValue::Str(format!("cond = ({cond})")),
+        )]);
+        let ctx = LineProcessContextRef {
+            game_props: &self.game.config.props,
+            frontend: self.frontend,
+            ctx: &self.ctx,
+            props: &props,
+            config: module.config(),
+        };
+        match module.recover(module.dispatch_line("exec", ctx))? {
+            Some(res) => Ok(res
+                .vars
+                .get("cond")
+                .map(|value| value.get_bool())
+                .unwrap_or_default()),
+            None => {
+                self.diagnose(
+                    DiagnosticKind::InvalidCondition,
+                    &self.ctx,
+                    None,
+                    format!("The evaluation of condition `{cond}` is skipped"),
+                )?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Merge the actions along the fallback chain.
+    /// The last one is the base language.
+    fn merge_action(&self, actions: Vec<Option<Action>>) -> Result<Action> {
+        let mut merged = None;
+        for action in actions.into_iter().rev() {
+            merged = match (action, merged) {
+                (None, merged) => merged,
+                (Some(action), None) => Some(action),
+                (Some(action), Some(action_base)) => {
+                    Some(self.merge_action_pair(action, action_base)?)
                 }
-                (Action::Custom(mut vars), Action::Custom(vars_base)) => {
-                    vars.extend(vars_base);
-                    Ok(Action::Custom(vars))
+            };
+        }
+        Ok(merged.unwrap_or_default())
+    }
+
+    fn merge_action_pair(&self, action: Action, action_base: Action) -> Result<Action> {
+        match (action, action_base) {
+            (Action::Text(action), Action::Text(action_base)) => {
+                let action = Fallback::new(Some(action), Some(action_base));
+                let action = action.spec();
+                Ok(Action::Text(ActionText {
+                    text: action.text.and_any().unwrap_or_default(),
+                    ch_key: action.ch_key.flatten().fallback(),
+                    character: action.character.flatten().fallback(),
+                    vars: action.vars.and_any().unwrap_or_default(),
+                }))
+            }
+            (Action::Switches(mut switches), Action::Switches(switches_base)) => {
+                for (item, item_base) in switches.iter_mut().zip(switches_base) {
+                    item.enabled = item_base.enabled;
+                    item.hidden = item_base.hidden;
+                    item.timeout = item_base.timeout;
                 }
-                _ => bail!("Mismatching action type"),
-            },
+                Ok(Action::Switches(switches))
+            }
+            (Action::Custom(mut vars), Action::Custom(vars_base)) => {
+                vars.extend(vars_base);
+                Ok(Action::Custom(vars))
+            }
+            _ => bail!("Mismatching action type"),
         }
     }
 
@@ -484,8 +923,11 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                 frontend: self.frontend,
                 ctx,
                 action,
+                config: module.config(),
             };
-            *action = module.process_action(ctx)?.action;
+            if let Some(res) = module.recover(module.process_action(ctx))? {
+                *action = res.action;
+            }
         }
         while let Some(act) = action.text.back() {
             if act.as_str().trim().is_empty() {
@@ -506,51 +948,163 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
 
     /// Get the [`Action`] from [`Locale`] and [`RawContext`].
     pub fn get_action(&self, loc: &Locale, ctx: &RawContext) -> Result<Action> {
-        let cur_text = self
+        self.get_action_with_source(loc, ctx)
+            .map(|(action, _)| action)
+    }
+
+    /// Get the [`Action`] from [`Locale`] and [`RawContext`],
+    /// and the level of the fallback chain where the line comes from.
+    ///
+    /// The source is [`None`] if the line is missing in all levels.
+    pub fn get_action_with_source(
+        &self,
+        loc: &Locale,
+        ctx: &RawContext,
+    ) -> Result<(Action, Option<ActionSource>)> {
+        let mut actions = vec![];
+        let mut source = None;
+        for (level, (para_loc, para)) in self
             .game
-            .find_para_fallback(loc, &ctx.cur_base_para, &ctx.cur_para)
-            .map(|p| p.texts.get(ctx.cur_act))
-            .flatten();
-
-        let action = cur_text
-            .map(|t| match t {
-                Line::Text(t) => self.parse_text(loc, t, ctx).map(Action::Text).ok(),
-                Line::Switch { switches } => Some(Action::Switches(self.parse_switches(switches))),
+            .find_para_chain(loc, &ctx.cur_base_para, &ctx.cur_para)
+            .enumerate()
+        {
+            let cur_text = para.and_then(|p| p.texts.get(ctx.cur_act));
+            let action = match cur_text.map(|t| t.inner()) {
+                Some(Line::Text(t)) => match self.parse_text(loc, t, ctx) {
+                    Ok(t) => Some(Action::Text(t)),
+                    Err(e) if self.options.strict => return Err(e),
+                    Err(_) => None,
+                },
+                Some(Line::Switch {
+                    switches,
+                    timeout,
+                    default,
+                }) => Some(Action::Switches(
+                    self.parse_switches(switches, *timeout, *default),
+                )),
                 // The real vars will be filled in `merge_action`.
-                Line::Custom(_) => Some(Action::Custom(self.vars.clone())),
+                Some(Line::Custom(_)) => Some(Action::Custom(self.vars.clone())),
                 _ => None,
-            })
-            .flatten();
+            };
+            // An empty text falls back to the next level.
+            let present = match &action {
+                Some(Action::Text(t)) => !t.text.is_empty(),
+                action => action.is_some(),
+            };
+            if source.is_none() && present {
+                source = Some(ActionSource {
+                    level,
+                    locale: para_loc.clone(),
+                });
+            }
+            actions.push(action);
+        }
 
-        let mut act = self.merge_action(action)?;
+        let mut act = self.merge_action(actions)?;
         if let Action::Text(act) = &mut act {
             self.process_action_text(ctx, act)?;
         }
-        Ok(act)
+        Ok((act, source))
     }
 
     /// Step to next line.
+    ///
+    /// The problems are recorded as [`Diagnostic`]s.
+    ///
+    /// It returns [`None`] at the end of the game,
+    /// or if the run stops because a plugin fails with [`TrapPolicy::Fail`],
+    /// or a [`Diagnostic`] is found in strict mode.
+    /// In the latter cases, the position is kept,
+    /// so that the failed line runs again in the next call.
     pub fn next_run(&mut self) -> Option<RawContext> {
         let cur_text_base = loop {
             let cur_para = self.current_paragraph(&self.game.config.base_lang);
             let cur_text = self.current_text(&self.game.config.base_lang);
             match (cur_para.is_some(), cur_text.is_some()) {
-                (true, true) => break cur_text,
+                (true, true) => {
+                    let cond = cur_text.and_then(|t| t.condition()).map(|c| c.to_string());
+                    if let Some(cond) = cond {
+                        let res = match self.check_condition(&cond) {
+                            Ok(res) => res,
+                            Err(e) => {
+                                self.stop_run(e);
+                                return None;
+                            }
+                        };
+                        if !res {
+                            self.ctx.cur_act += 1;
+                            continue;
+                        }
+                    }
+                    break cur_text;
+                }
                 (true, false) => {
-                    self.ctx.cur_para = cur_para
-                        .and_then(|p| p.next.as_ref())
-                        .map(|text| unwrap_or_default_log!(self.call(text), "Cannot get next para"))
-                        .unwrap_or_default();
-                    self.ctx.cur_act = 0;
+                    let next = match cur_para.and_then(|p| p.next.as_ref()) {
+                        Some(text) => match self.call(text) {
+                            Ok(next) => next,
+                            Err(e) => {
+                                self.stop_run(e);
+                                return None;
+                            }
+                        },
+                        None => String::new(),
+                    };
+                    // The end of a subroutine returns to the caller.
+                    let res = if next.is_empty() {
+                        match self.ctx.call_stack.last().cloned() {
+                            Some(frame) => self
+                                .jump(frame.cur_base_para, frame.cur_para, frame.cur_act)
+                                .map(|()| {
+                                    self.ctx.call_stack.pop();
+                                }),
+                            None => {
+                                self.ctx.cur_para = next;
+                                self.ctx.cur_act = 0;
+                                Ok(())
+                            }
+                        }
+                    } else {
+                        let base_para = self.ctx.cur_base_para.clone();
+                        self.jump(base_para, next, 0)
+                    };
+                    if let Err(e) = res {
+                        self.stop_run(e);
+                        return None;
+                    }
                 }
                 (false, _) => {
+                    if let Some(e) = self
+                        .game
+                        .para_file_error(&self.game.config.base_lang, &self.ctx.cur_base_para)
+                    {
+                        let message = format!("{e:#}");
+                        // The run ends anyway, even if not in strict mode.
+                        self.diagnose(
+                            DiagnosticKind::InvalidParagraphFile,
+                            &self.ctx,
+                            None,
+                            message,
+                        )
+                        .ok();
+                        self.end_paragraphs();
+                        return None;
+                    }
                     if self.ctx.cur_base_para == self.ctx.cur_para {
                         if !self.ctx.cur_para.is_empty() {
-                            error!(
+                            let message = format!(
                                 "Cannot find paragraph \"{}\"",
                                 self.ctx.cur_para.escape_default()
                             );
+                            // The run ends anyway, even if not in strict mode.
+                            self.diagnose(
+                                DiagnosticKind::MissingParagraph,
+                                &self.ctx,
+                                None,
+                                message,
+                            )
+                            .ok();
                         }
+                        self.end_paragraphs();
                         return None;
                     } else {
                         self.ctx.cur_base_para = self.ctx.cur_para.clone();
@@ -559,17 +1113,151 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             }
         };
 
-        let ctx = cur_text_base.cloned().map(|t| {
-            unwrap_or_default_log!(self.process_line(t), "Parse line error");
-            self.ctx.clone()
-        });
-        self.ctx.cur_act += 1;
+        let cur_text_base = cur_text_base.cloned();
+        if self.ctx.cur_base_para != self.ctx.entered_base_para
+            || self.ctx.cur_para != self.ctx.entered_para
+        {
+            let base_para = self.ctx.cur_base_para.clone();
+            let para = self.ctx.cur_para.clone();
+            if let Err(e) = self.transfer_paragraph(&base_para, &para) {
+                self.stop_run(e);
+                return None;
+            }
+        }
+        let call = match cur_text_base.as_ref().map(|t| t.inner()) {
+            Some(Line::Call { call }) => match self.call(call) {
+                Ok(tag) => Some(tag),
+                Err(e) => {
+                    self.stop_run(e);
+                    return None;
+                }
+            },
+            _ => None,
+        };
+        let ctx = match cur_text_base {
+            Some(t) => {
+                if let Err(e) = self.process_line(t) {
+                    self.stop_run(e);
+                    return None;
+                }
+                self.sync_rng();
+                self.coverage.update(&self.ctx);
+                Some(self.ctx.clone())
+            }
+            None => None,
+        };
+        match call {
+            Some(tag) => {
+                if let Err(e) = self.enter_call(tag) {
+                    self.stop_run(e);
+                    return None;
+                }
+            }
+            None => self.ctx.cur_act += 1,
+        }
         ctx
     }
 
+    /// Jump to the line `act` of the paragraph.
+    ///
+    /// The paragraph plugins are notified when the first line of a paragraph runs.
+    /// A jump to the entered paragraph, e.g., a `next` looping back,
+    /// keeps the paragraph the same, so they are notified here.
+    fn jump(&mut self, base_para: String, para: String, act: usize) -> Result<()> {
+        if base_para == self.ctx.entered_base_para && para == self.ctx.entered_para {
+            self.transfer_paragraph(&base_para, &para)?;
+        }
+        self.ctx.cur_base_para = base_para;
+        self.ctx.cur_para = para;
+        self.ctx.cur_act = act;
+        Ok(())
+    }
+
+    /// Notify the paragraph plugins that the game ends.
+    fn end_paragraphs(&mut self) {
+        if let Err(e) = self.transfer_paragraph("", "") {
+            self.stop_run(e);
+        }
+    }
+
+    /// Notify the paragraph plugins that the paragraph changes.
+    /// The exit hooks are called before the enter hooks.
+    ///
+    /// The entered paragraph is updated only if all hooks succeed.
+    fn transfer_paragraph(&mut self, to_base_para: &str, to: &str) -> Result<()> {
+        let from_base_para = self.ctx.entered_base_para.clone();
+        let from = self.ctx.entered_para.clone();
+        if !from.is_empty() {
+            self.call_paragraph_modules((&from_base_para, &from), (to_base_para, to), false)?;
+        }
+        if !to.is_empty() {
+            self.call_paragraph_modules((&from_base_para, &from), (to_base_para, to), true)?;
+        }
+        self.ctx.entered_base_para = to_base_para.to_string();
+        self.ctx.entered_para = to.to_string();
+        Ok(())
+    }
+
+    /// Call the paragraph hooks.
+    /// The paragraphs are pairs of the paragraph file and the tag.
+    fn call_paragraph_modules(
+        &mut self,
+        from: (&str, &str),
+        to: (&str, &str),
+        enter: bool,
+    ) -> Result<()> {
+        for module in self.runtime.paragraph_modules() {
+            let ctx = ParagraphProcessContextRef {
+                game_props: &self.game.config.props,
+                frontend: self.frontend,
+                ctx: &self.ctx,
+                from_base_para: from.0,
+                from: from.1,
+                to_base_para: to.0,
+                to: to.1,
+                config: module.config(),
+            };
+            let res = if enter {
+                module.on_paragraph_enter(ctx)
+            } else {
+                module.on_paragraph_exit(ctx)
+            };
+            if let Some(res) = module.recover(res)? {
+                self.ctx.locals = res.locals;
+            }
+        }
+        Ok(())
+    }
+
+    /// Call the subroutine paragraph after the current line.
+    fn enter_call(&mut self, tag: String) -> Result<()> {
+        if tag.is_empty() {
+            log::warn!("Empty called paragraph, ignored");
+            self.ctx.cur_act += 1;
+            return Ok(());
+        }
+        let frame = CallFrame {
+            cur_base_para: self.ctx.cur_base_para.clone(),
+            cur_para: self.ctx.cur_para.clone(),
+            cur_act: self.ctx.cur_act + 1,
+        };
+        let base_para = self.ctx.cur_base_para.clone();
+        self.jump(base_para, tag, 0)?;
+        self.ctx.call_stack.push(frame);
+        Ok(())
+    }
+
     /// Get current paragraph title.
     pub fn current_paragraph_title(&self, loc: &Locale) -> Option<&String> {
-        self.current_paragraph_fallback(loc)
-            .and_then(|p| p.title.as_ref())
+        self.game
+            .find_para_chain(loc, &self.ctx.cur_base_para, &self.ctx.cur_para)
+            .find_map(|(_, p)| p.and_then(|p| p.title.as_ref()))
     }
 }
+
+/// Whether the line or its switches have conditions.
+fn has_condition(line: &Line) -> bool {
+    line.condition().is_some()
+        || matches!(line.inner(), Line::Switch { switches, .. }
+            if switches.iter().any(|item| item.cond.is_some() || item.hidden_if.is_some()))
+}
@@ -0,0 +1,159 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The executed lines of the game.
+///
+/// The lines are keyed by the paragraph file, the paragraph tag
+/// and the line index, the same as [`RawContext`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Coverage {
+    /// The key is the paragraph file, then the paragraph tag,
+    /// and the value is the indices of executed lines.
+    pub record: BTreeMap<String, BTreeMap<String, BTreeSet<usize>>>,
+}
+
+impl Coverage {
+    /// Determine if the line of a [`RawContext`] has been executed.
+    pub fn visited(&self, ctx: &RawContext) -> bool {
+        self.record
+            .get(&ctx.cur_base_para)
+            .and_then(|file| file.get(&ctx.cur_para))
+            .map(|lines| lines.contains(&ctx.cur_act))
+            .unwrap_or_default()
+    }
+
+    /// Record the line of a [`RawContext`].
+    pub fn update(&mut self, ctx: &RawContext) {
+        self.record
+            .entry(ctx.cur_base_para.clone())
+            .or_default()
+            .entry(ctx.cur_para.clone())
+            .or_default()
+            .insert(ctx.cur_act);
+    }
+
+    /// Merge the coverage of another session.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (base_tag, file) in &other.record {
+            let this_file = self.record.entry(base_tag.clone()).or_default();
+            for (tag, lines) in file {
+                this_file
+                    .entry(tag.clone())
+                    .or_default()
+                    .extend(lines.iter().copied());
+            }
+        }
+    }
+
+    /// Generate a report against the paragraphs of base language.
+    pub fn report(&self, game: &Game) -> CoverageReport {
+        let mut paras = game
+            .para_files(&game.config.base_lang)
+            .flat_map(|(base_tag, file)| {
+                file.paras().iter().map(move |p| {
+                    let visited = self.record.get(base_tag).and_then(|file| file.get(&p.tag));
+                    let unvisited = (0..p.texts.len())
+                        .filter(|act| !visited.map(|v| v.contains(act)).unwrap_or_default())
+                        .collect();
+                    ParagraphCoverage {
+                        base_para: base_tag.clone(),
+                        para: p.tag.clone(),
+                        total: p.texts.len(),
+                        unvisited,
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        paras.sort_by(|a, b| (&a.base_para, &a.para).cmp(&(&b.base_para, &b.para)));
+        CoverageReport { paras }
+    }
+}
+
+/// The coverage of a paragraph.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParagraphCoverage {
+    /// The paragraph file.
+    pub base_para: String,
+    /// The paragraph tag.
+    pub para: String,
+    /// The count of lines.
+    pub total: usize,
+    /// The indices of lines never executed.
+    pub unvisited: Vec<usize>,
+}
+
+impl ParagraphCoverage {
+    /// The count of executed lines.
+    pub fn visited(&self) -> usize {
+        self.total - self.unvisited.len()
+    }
+
+    /// The percentage of executed lines.
+    /// An empty paragraph is treated as fully covered.
+    pub fn percent(&self) -> f64 {
+        percent(self.visited(), self.total)
+    }
+}
+
+/// The coverage report of the game.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct CoverageReport {
+    /// The paragraphs, sorted by file and tag.
+    pub paras: Vec<ParagraphCoverage>,
+}
+
+impl CoverageReport {
+    /// The count of lines.
+    pub fn total(&self) -> usize {
+        self.paras.iter().map(|p| p.total).sum()
+    }
+
+    /// The count of executed lines.
+    pub fn visited(&self) -> usize {
+        self.paras.iter().map(|p| p.visited()).sum()
+    }
+
+    /// The percentage of executed lines.
+    pub fn percent(&self) -> f64 {
+        percent(self.visited(), self.total())
+    }
+}
+
+fn percent(visited: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        visited as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    fn raw_ctx(base_para: &str, para: &str, act: usize) -> RawContext {
+        RawContext {
+            cur_base_para: base_para.to_string(),
+            cur_para: para.to_string(),
+            cur_act: act,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = Coverage::default();
+        a.update(&raw_ctx("init", "init", 0));
+        a.update(&raw_ctx("init", "init", 1));
+        let mut b = Coverage::default();
+        b.update(&raw_ctx("init", "init", 2));
+        b.update(&raw_ctx("end", "bad", 0));
+        a.merge(&b);
+        assert!(a.visited(&raw_ctx("init", "init", 0)));
+        assert!(a.visited(&raw_ctx("init", "init", 2)));
+        assert!(a.visited(&raw_ctx("end", "bad", 0)));
+        assert!(!a.visited(&raw_ctx("end", "bad", 1)));
+        assert!(!a.visited(&raw_ctx("end", "good", 0)));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::*;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// The kind of [`Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum DiagnosticKind {
+    /// The count of arguments of a command is wrong.
+    InvalidArgCount,
+    /// The resource referenced by `\res` is missing.
+    MissingResource,
+    /// The variable referenced by `\var` is missing.
+    MissingVariable,
+    /// No plugin handles the text or line command.
+    UnknownCommand,
+    /// The paragraph file cannot be loaded lazily.
+    InvalidParagraphFile,
+    /// The paragraph to run is missing, e.g., the one of `next`,
+    /// and the run ends.
+    MissingParagraph,
+    /// The condition of a line or a switch cannot be evaluated,
+    /// and it is treated as false.
+    InvalidCondition,
+    /// A plugin call fails with [`TrapPolicy::Fail`],
+    /// and the run stops.
+    PluginFailure,
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::InvalidArgCount => "invalid argument count",
+            Self::MissingResource => "missing resource",
+            Self::MissingVariable => "missing variable",
+            Self::UnknownCommand => "unknown command",
+            Self::InvalidParagraphFile => "invalid paragraph file",
+            Self::MissingParagraph => "missing paragraph",
+            Self::InvalidCondition => "invalid condition",
+            Self::PluginFailure => "plugin failure",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A problem found when evaluating texts, conditions or loading paragraphs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// The kind of the problem.
+    pub kind: DiagnosticKind,
+    /// The paragraph file.
+    pub base_para: String,
+    /// The paragraph tag.
+    pub para: String,
+    /// The index of the line.
+    /// It equals to the count of lines when evaluating `next`.
+    pub act: usize,
+    /// The locale of the text.
+    /// It is [`None`] when evaluating scripts like `next` and `goto`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<Locale>,
+    /// The detailed message.
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(
+        kind: DiagnosticKind,
+        ctx: &RawContext,
+        loc: Option<&Locale>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            kind,
+            base_para: ctx.cur_base_para.clone(),
+            para: ctx.cur_para.clone(),
+            act: ctx.cur_act,
+            locale: loc.cloned(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}:{}", self.base_para, self.para, self.act)?;
+        if let Some(loc) = &self.locale {
+            write!(f, " ({loc})")?;
+        }
+        write!(f, ": {}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
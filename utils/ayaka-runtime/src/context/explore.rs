@@ -0,0 +1,246 @@
+use crate::*;
+use std::collections::{BTreeSet, HashSet};
+
+/// Options of [`Context::explore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExploreOptions {
+    /// The max count of choices in one path.
+    /// The paths are truncated when reaching the bound.
+    pub max_depth: usize,
+    /// The max count of lines to run in total.
+    /// The exploration stops when reaching the bound.
+    pub max_steps: usize,
+}
+
+impl Default for ExploreOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_steps: 1_000_000,
+        }
+    }
+}
+
+/// A `next` resolving to a missing paragraph.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MissingNext {
+    /// The paragraph whose `next` is missing.
+    /// It is [`None`] if the start paragraph is missing.
+    pub from: Option<ParagraphKey>,
+    /// The missing tag.
+    pub target: String,
+}
+
+/// A line in a paragraph.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineKey {
+    /// The paragraph.
+    pub para: ParagraphKey,
+    /// The index of the line.
+    pub act: usize,
+}
+
+/// The report of [`Context::explore`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExploreReport {
+    /// Paragraphs never reached from the start.
+    /// Paragraphs without any line are not reported.
+    pub unreachable: Vec<ParagraphKey>,
+    /// `next` resolving to missing paragraphs.
+    pub missing_next: Vec<MissingNext>,
+    /// Choice points where every switch is disabled or hidden.
+    pub dead_switches: Vec<LineKey>,
+    /// Lines where the game loops forever without any choice.
+    pub infinite_loops: Vec<LineKey>,
+    /// Count of paths reaching the end.
+    pub endings: usize,
+    /// Count of paths truncated by [`ExploreOptions::max_depth`].
+    pub truncated: usize,
+    /// Whether the exploration stopped by [`ExploreOptions::max_steps`].
+    pub aborted: bool,
+}
+
+impl ExploreReport {
+    /// Returns `true` if no problem is found.
+    pub fn is_ok(&self) -> bool {
+        self.unreachable.is_empty()
+            && self.missing_next.is_empty()
+            && self.dead_switches.is_empty()
+            && self.infinite_loops.is_empty()
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct StateKey {
+    cur_base_para: String,
+    cur_para: String,
+    cur_act: usize,
+    locals: Vec<(String, RawValue)>,
+    call_stack: Vec<CallFrame>,
+}
+
+impl From<&RawContext> for StateKey {
+    fn from(ctx: &RawContext) -> Self {
+        // The `?` locals of the last choice persist after it,
+        // and they are not counted, so that the paths could merge.
+        let mut locals = ctx
+            .locals
+            .iter()
+            .filter(|(key, _)| !key.starts_with('?'))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        locals.sort();
+        Self {
+            cur_base_para: ctx.cur_base_para.clone(),
+            cur_para: ctx.cur_para.clone(),
+            cur_act: ctx.cur_act,
+            locals,
+            call_stack: ctx.call_stack.clone(),
+        }
+    }
+}
+
+fn line_key(ctx: &RawContext) -> LineKey {
+    LineKey {
+        para: ParagraphKey::new(&ctx.cur_base_para, &ctx.cur_para),
+        act: ctx.cur_act,
+    }
+}
+
+impl<M: RawModule + Send + Sync + 'static> Context<M> {
+    fn is_switch_line(&self, ctx: &RawContext) -> bool {
+        matches!(
+            self.game
+                .find_para(
+                    &self.game.config.base_lang,
+                    &ctx.cur_base_para,
+                    &ctx.cur_para
+                )
+                .and_then(|p| p.texts.get(ctx.cur_act))
+                .map(|line| line.inner()),
+            Some(Line::Switch { .. })
+        )
+    }
+
+    /// Explore all choice paths from the start of the game.
+    ///
+    /// The [`RawContext`] is forked at every [`Action::Switches`],
+    /// and every enabled item is tried.
+    /// The states are keyed by the position, the locals and the call stack,
+    /// so the same state is explored only once.
+    /// The `?` locals are not in the key, but the line after a choice always runs,
+    /// so that `$?` could be read there.
+    ///
+    /// The current [`RawContext`], the states of the random number generator
+    /// and the state plugins, and the [`Diagnostic`]s are restored after exploring,
+    /// and the explored lines are not counted in the [`Coverage`].
+    /// The files written to the data directory by plugins are not restored.
+    pub fn explore(&mut self, options: ExploreOptions) -> ExploreReport {
+        let coverage = std::mem::take(&mut self.coverage);
+        let ctx = self.ctx.clone();
+        let rng = self.runtime.rng_state();
+        let switches = self.switches.clone();
+        let switch_default = self.switch_default;
+        let plugin_states = self.plugin_states();
+        let diagnostics = self.diagnostics.lock().unwrap().clone();
+        let mut report = ExploreReport::default();
+        let mut visited_paras = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut missing_next = BTreeSet::new();
+        let mut dead_switches = BTreeSet::new();
+        let mut infinite_loops = BTreeSet::new();
+        let mut steps = 0usize;
+
+        let mut stack = vec![(self.game.start_context(), 0usize)];
+        'paths: while let Some((ctx, depth)) = stack.pop() {
+            self.set_context(ctx);
+            let mut segment = HashSet::new();
+            let mut last_para = None;
+            let mut chosen = depth > 0;
+            loop {
+                if steps >= options.max_steps {
+                    report.aborted = true;
+                    break 'paths;
+                }
+                steps += 1;
+
+                let key = StateKey::from(&self.ctx);
+                if segment.contains(&key) {
+                    infinite_loops.insert(line_key(&self.ctx));
+                    continue 'paths;
+                }
+                if !visited.insert(key) && !chosen {
+                    continue 'paths;
+                }
+                chosen = false;
+                segment.insert(StateKey::from(&self.ctx));
+
+                let Some(raw_ctx) = self.next_run() else {
+                    if !self.ctx.cur_para.is_empty() {
+                        missing_next.insert(MissingNext {
+                            from: last_para,
+                            target: self.ctx.cur_para.clone(),
+                        });
+                    } else {
+                        report.endings += 1;
+                    }
+                    continue 'paths;
+                };
+                let para = ParagraphKey::new(&raw_ctx.cur_base_para, &raw_ctx.cur_para);
+                visited_paras.insert(para.clone());
+                last_para = Some(para);
+
+                if self.is_switch_line(&raw_ctx) {
+                    let switches = self.switches.clone();
+                    if !switches.iter().any(|state| state.available()) {
+                        dead_switches.insert(line_key(&raw_ctx));
+                        continue 'paths;
+                    }
+                    if depth >= options.max_depth {
+                        report.truncated += 1;
+                        continue 'paths;
+                    }
+                    let base_ctx = self.ctx.clone();
+                    for (i, state) in switches.iter().enumerate().rev() {
+                        if state.available() {
+                            self.set_context(base_ctx.clone());
+                            self.switches.clone_from(&switches);
+                            match self.switch(i) {
+                                Ok(()) => stack.push((self.ctx.clone(), depth + 1)),
+                                Err(e) => log::warn!("Cannot choose switch {i}: {e}"),
+                            }
+                        }
+                    }
+                    continue 'paths;
+                }
+            }
+        }
+
+        let base_lang = &self.game.config.base_lang;
+        let mut unreachable = self
+            .game
+            .para_files(base_lang)
+            .flat_map(|(base_tag, file)| {
+                file.paras()
+                    .iter()
+                    .filter(|p| !p.texts.is_empty())
+                    .map(move |p| ParagraphKey::new(base_tag, &p.tag))
+            })
+            .filter(|key| !visited_paras.contains(key))
+            .collect::<Vec<_>>();
+        unreachable.sort();
+        report.unreachable = unreachable;
+        report.missing_next = missing_next.into_iter().collect();
+        report.dead_switches = dead_switches.into_iter().collect();
+        report.infinite_loops = infinite_loops.into_iter().collect();
+
+        self.set_context(ctx);
+        self.runtime.set_rng_state(rng);
+        self.switches = switches;
+        self.switch_default = switch_default;
+        self.set_plugin_states(&plugin_states);
+        *self.diagnostics.lock().unwrap() = diagnostics;
+        self.coverage = coverage;
+        report
+    }
+}
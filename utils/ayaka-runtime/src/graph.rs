@@ -0,0 +1,629 @@
+use crate::*;
+use ayaka_script::{BinaryOp, Expr, Program, Ref};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+};
+
+/// The key of a paragraph, the file name and the tag.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct ParagraphKey {
+    /// The file name of the paragraph, without extension.
+    pub base_tag: String,
+    /// The tag of the paragraph.
+    pub tag: String,
+}
+
+impl ParagraphKey {
+    /// Create a new [`ParagraphKey`].
+    pub fn new(base_tag: impl Into<String>, tag: impl Into<String>) -> Self {
+        Self {
+            base_tag: base_tag.into(),
+            tag: tag.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParagraphKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.base_tag, self.tag)
+    }
+}
+
+/// An `exec` line assigning a context variable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphAssign {
+    /// The paragraph containing the line.
+    pub para: ParagraphKey,
+    /// The index of the line.
+    pub line: usize,
+    /// The script of the line.
+    pub script: String,
+    /// The string literals which may be assigned to the variable.
+    pub values: Vec<String>,
+}
+
+/// The `next` of a paragraph.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum GraphNext {
+    /// No `next`, the game meets the end.
+    End,
+    /// A static paragraph tag.
+    Static(String),
+    /// A tag decided by a context variable, `\var{...}`.
+    Dynamic {
+        /// The variable name.
+        var: String,
+        /// The `exec` lines assigning the variable.
+        assigns: Vec<GraphAssign>,
+    },
+}
+
+/// A paragraph node in the [`StoryGraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphParagraph {
+    /// The key of the paragraph.
+    pub key: ParagraphKey,
+    /// The title of the paragraph.
+    pub title: Option<String>,
+    /// The count of lines.
+    pub lines: usize,
+    /// The `next` of the paragraph.
+    pub next: GraphNext,
+}
+
+/// A switch node in the [`StoryGraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphSwitch {
+    /// The paragraph containing the switches.
+    pub para: ParagraphKey,
+    /// The index of the line.
+    pub line: usize,
+    /// The switch items.
+    pub items: Vec<String>,
+}
+
+/// The kind of a [`GraphEdge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum GraphEdgeKind {
+    /// A static `next`.
+    Next,
+    /// A possible value of a dynamic `next`.
+    Dynamic,
+    /// A subroutine call, which returns to the caller.
+    Call,
+    /// The `goto` of a switch item.
+    Goto,
+}
+
+/// A paragraph transition in the [`StoryGraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphEdge {
+    /// The source paragraph.
+    pub from: ParagraphKey,
+    /// The target tag as written in the config.
+    pub target: String,
+    /// The resolved target paragraph.
+    /// It is [`None`] if the target cannot be found.
+    pub to: Option<ParagraphKey>,
+    /// The kind of the transition.
+    pub kind: GraphEdgeKind,
+}
+
+/// The static story graph of a [`Game`].
+///
+/// The structure is taken from the paragraphs of the base language.
+/// Dynamic `next` are resolved by looking into the `exec` lines
+/// assigning the variable, so only string literals are recognized.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StoryGraph {
+    /// The paragraphs, ordered by file name and the order in file.
+    pub paras: Vec<GraphParagraph>,
+    /// The switches.
+    pub switches: Vec<GraphSwitch>,
+    /// The transitions between paragraphs.
+    pub edges: Vec<GraphEdge>,
+}
+
+fn next_var(text: &Text) -> Option<String> {
+    match text.sub_texts.as_slice() {
+        [SubText::Cmd(cmd, args)] if cmd == "var" => match args.as_slice() {
+            [SubText::Str(name)] => Some(name.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn static_text(text: &Text) -> Option<String> {
+    let mut str = String::new();
+    for sub_text in &text.sub_texts {
+        match sub_text {
+            SubText::Char(c) => str.push(*c),
+            SubText::Str(s) => str.push_str(s),
+            SubText::Cmd(_, _) => return None,
+        }
+    }
+    Some(str.trim().to_string())
+}
+
+fn collect_str(expr: &Expr, values: &mut Vec<String>) {
+    match expr {
+        Expr::Const(RawValue::Str(s)) => {
+            if !values.contains(s) {
+                values.push(s.clone())
+            }
+        }
+        Expr::Unary(_, expr) => collect_str(expr, values),
+        Expr::Binary(lhs, _, rhs) => {
+            collect_str(lhs, values);
+            collect_str(rhs, values);
+        }
+        Expr::Call(_, _, args) => args.iter().for_each(|arg| collect_str(arg, values)),
+        Expr::Ref(_) | Expr::Const(_) => {}
+    }
+}
+
+fn collect_assigns(expr: &Expr, var: &str, values: &mut Vec<String>) -> bool {
+    match expr {
+        Expr::Binary(lhs, op, rhs) => {
+            let mut found = false;
+            if let (Expr::Ref(Ref::Ctx(name)), BinaryOp::Assign) = (lhs.as_ref(), op) {
+                if name == var {
+                    collect_str(rhs, values);
+                    found = true;
+                }
+            }
+            found | collect_assigns(rhs, var, values)
+        }
+        Expr::Unary(_, expr) => collect_assigns(expr, var, values),
+        Expr::Call(_, _, args) => args.iter().fold(false, |found, arg| {
+            found | collect_assigns(arg, var, values)
+        }),
+        Expr::Ref(_) | Expr::Const(_) => false,
+    }
+}
+
+fn exec_script(line: &Line) -> Option<&str> {
+    match line.inner() {
+        Line::Custom(props) => match props.get("exec") {
+            Some(RawValue::Str(script)) => Some(script),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl Game {
+    fn sorted_paras(&self, loc: &Locale) -> Vec<(&String, &[Paragraph])> {
+        let mut files = self
+            .para_files(loc)
+            .map(|(name, file)| (name, file.paras()))
+            .collect::<Vec<_>>();
+        files.sort_by_key(|(name, _)| *name);
+        files
+    }
+
+    /// Resolve the target paragraph of `next`, the same way as [`Context::next_run`].
+    ///
+    /// The paragraph is first searched in the current file,
+    /// and then the file with the same name as the tag.
+    pub fn resolve_next(&self, base_tag: &str, tag: &str) -> Option<ParagraphKey> {
+        let loc = &self.config.base_lang;
+        if self.find_para(loc, base_tag, tag).is_some() {
+            Some(ParagraphKey::new(base_tag, tag))
+        } else if self.find_para(loc, tag, tag).is_some() {
+            Some(ParagraphKey::new(tag, tag))
+        } else {
+            None
+        }
+    }
+
+    fn find_assigns(&self, key: &ParagraphKey, para: &Paragraph, var: &str) -> Vec<GraphAssign> {
+        let find_in = |key: &ParagraphKey, para: &Paragraph, assigns: &mut Vec<GraphAssign>| {
+            for (i, line) in para.texts.iter().enumerate() {
+                if let Some(script) = exec_script(line) {
+                    match script.parse::<Program>() {
+                        Ok(program) => {
+                            let mut values = vec![];
+                            let found = program.0.iter().fold(false, |found, expr| {
+                                found | collect_assigns(expr, var, &mut values)
+                            });
+                            if found {
+                                assigns.push(GraphAssign {
+                                    para: key.clone(),
+                                    line: i,
+                                    script: script.trim().to_string(),
+                                    values,
+                                });
+                            }
+                        }
+                        Err(e) => log::warn!("Cannot parse script in {key}:{i}: {e}"),
+                    }
+                }
+            }
+        };
+        let mut assigns = vec![];
+        find_in(key, para, &mut assigns);
+        // The variable may be assigned in another paragraph.
+        if assigns.is_empty() {
+            for (base_tag, paras) in self.sorted_paras(&self.config.base_lang) {
+                for p in paras {
+                    if base_tag != &key.base_tag || p.tag != key.tag {
+                        find_in(&ParagraphKey::new(base_tag, &p.tag), p, &mut assigns);
+                    }
+                }
+            }
+        }
+        assigns
+    }
+
+    fn text_targets(&self, key: &ParagraphKey, para: &Paragraph, text: &Text) -> BTreeSet<String> {
+        if let Some(var) = next_var(text) {
+            self.find_assigns(key, para, &var)
+                .into_iter()
+                .flat_map(|assign| assign.values)
+                .collect()
+        } else {
+            static_text(text).into_iter().collect()
+        }
+    }
+
+    /// Build the [`StoryGraph`] of the game.
+    ///
+    /// The titles and switch items are taken from `loc`,
+    /// and fallback along the fallback chain of the locale.
+    pub fn story_graph(&self, loc: &Locale) -> StoryGraph {
+        let mut graph = StoryGraph::default();
+        for (base_tag, paras) in self.sorted_paras(&self.config.base_lang) {
+            for para in paras {
+                let key = ParagraphKey::new(base_tag, &para.tag);
+                let para_chain = self
+                    .find_para_chain(loc, base_tag, &para.tag)
+                    .filter_map(|(_, p)| p)
+                    .collect::<Vec<_>>();
+                for (i, line) in para.texts.iter().enumerate() {
+                    let line = line.inner();
+                    if let Line::Switch { switches, .. } = line {
+                        let items = para_chain
+                            .iter()
+                            .find_map(|p| match p.texts.get(i).map(|line| line.inner()) {
+                                Some(Line::Switch { switches, .. }) => Some(switches),
+                                _ => None,
+                            })
+                            .unwrap_or(switches)
+                            .iter()
+                            .map(|item| item.text.clone())
+                            .collect();
+                        graph.switches.push(GraphSwitch {
+                            para: key.clone(),
+                            line: i,
+                            items,
+                        });
+                        for goto in switches.iter().filter_map(|item| item.goto.as_ref()) {
+                            for tag in self.text_targets(&key, para, goto) {
+                                graph.edges.push(GraphEdge {
+                                    from: key.clone(),
+                                    to: self.resolve_next(base_tag, &tag),
+                                    target: tag,
+                                    kind: GraphEdgeKind::Goto,
+                                })
+                            }
+                        }
+                    } else if let Line::Call { call } = line {
+                        for tag in self.text_targets(&key, para, call) {
+                            graph.edges.push(GraphEdge {
+                                from: key.clone(),
+                                to: self.resolve_next(base_tag, &tag),
+                                target: tag,
+                                kind: GraphEdgeKind::Call,
+                            })
+                        }
+                    }
+                }
+                let next = match &para.next {
+                    None => GraphNext::End,
+                    Some(text) => {
+                        if let Some(var) = next_var(text) {
+                            let assigns = self.find_assigns(&key, para, &var);
+                            GraphNext::Dynamic { var, assigns }
+                        } else if let Some(tag) = static_text(text) {
+                            if tag.is_empty() {
+                                GraphNext::End
+                            } else {
+                                GraphNext::Static(tag)
+                            }
+                        } else {
+                            log::warn!("Cannot analyze the next of {key}");
+                            GraphNext::End
+                        }
+                    }
+                };
+                match &next {
+                    GraphNext::End => {}
+                    GraphNext::Static(tag) => graph.edges.push(GraphEdge {
+                        from: key.clone(),
+                        target: tag.clone(),
+                        to: self.resolve_next(base_tag, tag),
+                        kind: GraphEdgeKind::Next,
+                    }),
+                    GraphNext::Dynamic { assigns, .. } => {
+                        let targets = assigns
+                            .iter()
+                            .flat_map(|assign| assign.values.iter())
+                            .collect::<BTreeSet<_>>();
+                        for tag in targets {
+                            graph.edges.push(GraphEdge {
+                                from: key.clone(),
+                                target: tag.clone(),
+                                to: self.resolve_next(base_tag, tag),
+                                kind: GraphEdgeKind::Dynamic,
+                            })
+                        }
+                    }
+                }
+                graph.paras.push(GraphParagraph {
+                    key,
+                    title: para_chain.iter().find_map(|p| p.title.clone()),
+                    lines: para.texts.len(),
+                    next,
+                });
+            }
+        }
+        graph
+    }
+}
+
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+impl StoryGraph {
+    fn para_label(para: &GraphParagraph) -> String {
+        match &para.title {
+            Some(title) => format!("{}\n{}", para.key, title),
+            None => para.key.to_string(),
+        }
+    }
+
+    fn missing_targets(&self) -> Vec<&str> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.to.is_none())
+            .map(|edge| edge.target.as_str())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Export the graph in Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for para in &self.paras {
+            let shape = if para.next == GraphNext::End {
+                "doublecircle"
+            } else {
+                "box"
+            };
+            writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\", shape={}];",
+                escape_label(&para.key.to_string()),
+                escape_label(&Self::para_label(para)),
+                shape
+            )
+            .unwrap();
+        }
+        for target in self.missing_targets() {
+            writeln!(
+                dot,
+                "    \"?{0}\" [label=\"{0}\", color=red];",
+                escape_label(target)
+            )
+            .unwrap();
+        }
+        for (i, switch) in self.switches.iter().enumerate() {
+            writeln!(
+                dot,
+                "    \"#{}\" [label=\"{}\", shape=diamond];",
+                i,
+                escape_label(&switch.items.join("\n"))
+            )
+            .unwrap();
+            writeln!(
+                dot,
+                "    \"{}\" -> \"#{}\" [style=dashed, label=\"{}\"];",
+                escape_label(&switch.para.to_string()),
+                i,
+                switch.line
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            let to = match &edge.to {
+                Some(to) => to.to_string(),
+                None => format!("?{}", edge.target),
+            };
+            let style = match edge.kind {
+                GraphEdgeKind::Next => "",
+                GraphEdgeKind::Dynamic => " [style=bold, color=blue]",
+                GraphEdgeKind::Call => " [style=dashed, color=darkgreen, label=call]",
+                GraphEdgeKind::Goto => " [color=orange, label=goto]",
+            };
+            writeln!(
+                dot,
+                "    \"{}\" -> \"{}\"{};",
+                escape_label(&edge.from.to_string()),
+                escape_label(&to),
+                style
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Export the graph in Mermaid flowchart format.
+    pub fn to_mermaid(&self) -> String {
+        let mut ids = HashMap::new();
+        let mut mermaid = String::from("flowchart TD\n");
+        for (i, para) in self.paras.iter().enumerate() {
+            ids.insert(para.key.to_string(), format!("p{i}"));
+            writeln!(
+                mermaid,
+                "    p{}[\"{}\"]",
+                i,
+                escape_mermaid(&Self::para_label(para)).replace('\n', "<br/>")
+            )
+            .unwrap();
+        }
+        for (i, target) in self.missing_targets().into_iter().enumerate() {
+            ids.insert(format!("?{target}"), format!("x{i}"));
+            writeln!(
+                mermaid,
+                "    x{}[\"{}\"]:::missing",
+                i,
+                escape_mermaid(target)
+            )
+            .unwrap();
+        }
+        for (i, switch) in self.switches.iter().enumerate() {
+            writeln!(
+                mermaid,
+                "    s{}{{\"{}\"}}",
+                i,
+                escape_mermaid(&switch.items.join("<br/>"))
+            )
+            .unwrap();
+            writeln!(mermaid, "    {} -.-> s{}", ids[&switch.para.to_string()], i).unwrap();
+        }
+        for edge in &self.edges {
+            let to = match &edge.to {
+                Some(to) => to.to_string(),
+                None => format!("?{}", edge.target),
+            };
+            let arrow = match edge.kind {
+                GraphEdgeKind::Next => "-->",
+                GraphEdgeKind::Dynamic => "==>",
+                GraphEdgeKind::Call => "-- call -->",
+                GraphEdgeKind::Goto => "-- goto -->",
+            };
+            writeln!(
+                mermaid,
+                "    {} {} {}",
+                ids[&edge.from.to_string()],
+                arrow,
+                ids[&to]
+            )
+            .unwrap();
+        }
+        mermaid.push_str("    classDef missing stroke:#f00\n");
+        mermaid
+    }
+
+    /// Export the graph in JSON format.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+    use std::collections::HashMap;
+
+    fn game() -> Game {
+        let paras: ParagraphFile = serde_yaml::from_str(
+            r#"
+- tag: main
+  title: Main
+  texts:
+    - Hello
+    - switches:
+      - a
+      - b
+    - exec: $next = if($? == 0, "a", "b")
+  next: \var{next}
+- tag: a
+  texts:
+    - A
+  next: other
+- tag: b
+  texts:
+    - B
+  next: nowhere
+"#,
+        )
+        .unwrap();
+        let others: ParagraphFile = serde_yaml::from_str(
+            r#"
+- tag: other
+  texts:
+    - Other
+"#,
+        )
+        .unwrap();
+        Game::new(
+            GameConfig {
+                base_lang: locale!("en"),
+                start: "main".to_string(),
+                ..Default::default()
+            },
+            HashMap::from([(
+                locale!("en"),
+                HashMap::from([("main".to_string(), paras), ("other".to_string(), others)]),
+            )]),
+            HashMap::default(),
+        )
+    }
+
+    #[test]
+    fn graph() {
+        let game = game();
+        let graph = game.story_graph(&locale!("en"));
+        assert_eq!(graph.paras.len(), 4);
+        assert_eq!(graph.switches.len(), 1);
+        assert_eq!(graph.switches[0].items, ["a", "b"]);
+
+        let main = ParagraphKey::new("main", "main");
+        let edges = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.clone(), edge.to.clone(), edge.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            [
+                (
+                    main.clone(),
+                    Some(ParagraphKey::new("main", "a")),
+                    GraphEdgeKind::Dynamic
+                ),
+                (
+                    main,
+                    Some(ParagraphKey::new("main", "b")),
+                    GraphEdgeKind::Dynamic
+                ),
+                (
+                    ParagraphKey::new("main", "a"),
+                    Some(ParagraphKey::new("other", "other")),
+                    GraphEdgeKind::Next
+                ),
+                (ParagraphKey::new("main", "b"), None, GraphEdgeKind::Next),
+            ]
+        );
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"main/main\" -> \"main/a\""));
+        assert!(dot.contains("\"?nowhere\""));
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.contains("p0 ==> p1"));
+        assert!(graph.to_json().is_ok());
+    }
+}
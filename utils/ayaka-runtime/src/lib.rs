@@ -10,6 +10,7 @@
 
 mod config;
 mod context;
+mod graph;
 mod locale;
 pub mod plugin;
 
@@ -18,13 +19,16 @@ pub use anyhow;
 #[doc(no_inline)]
 pub use ayaka_bindings_types::*;
 #[doc(no_inline)]
-pub use ayaka_plugin::{Linker, RawModule};
+pub use ayaka_plugin::{
+    Linker, LinkerConfig, ModuleLimits, PluginError, PluginErrorKind, RawModule,
+};
 #[doc(no_inline)]
 pub use ayaka_primitive::*;
 pub use config::*;
 pub use context::*;
 #[doc(no_inline)]
 pub use futures_util::{StreamExt, TryStreamExt};
+pub use graph::*;
 #[doc(no_inline)]
 pub use locale::*;
 #[doc(no_inline)]
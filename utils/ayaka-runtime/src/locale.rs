@@ -94,8 +94,6 @@ macro_rules! locale {
 
 #[cfg(test)]
 mod test {
-    use crate::locale;
-
     #[test]
     fn parse() {
         assert_eq!(locale!("zh-Hans").to_string(), "zh-Hans");
@@ -10,35 +10,122 @@ mod script_interop;
 mod test;
 
 use crate::*;
+use anyhow::bail;
 use ayaka_plugin::*;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock, Weak},
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock, Weak,
+    },
 };
 use stream_future::stream;
 use trylog::macros::*;
 use vfs::*;
 
 /// The plugin module with high-level interfaces.
+///
+/// A module exceeding its execution budget, or trapping with [`TrapPolicy::Disable`],
+/// is disabled, and the [`Runtime`] skips it from then on.
 pub struct Module<M: RawModule> {
     module: PluginModule<M>,
+    config: VarMap,
+    policy: TrapPolicy,
+    disabled: AtomicBool,
 }
 
 impl<M: RawModule> Module<M> {
-    fn new(module: M) -> Self {
+    fn new(module: M, config: VarMap, policy: TrapPolicy) -> Self {
         Self {
             module: PluginModule::new(module),
+            config,
+            policy,
+            disabled: AtomicBool::new(false),
         }
     }
 
+    /// The config of the module in [`PluginConfig::modules`].
+    /// It should be passed to the module in the process contexts.
+    pub fn config(&self) -> &VarMap {
+        &self.config
+    }
+
+    /// Determine if the module has been disabled.
+    pub fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Acquire)
+    }
+
+    /// The [`TrapPolicy`] of the module.
+    pub fn trap_policy(&self) -> TrapPolicy {
+        self.policy
+    }
+
+    fn call<P: Serialize, R: DeserializeOwned>(&self, name: &str, args: P) -> Result<R> {
+        self.call_with_args(name, args, Vec::new)
+    }
+
+    /// Call a method, and fill the arguments of [`PluginError`] if it fails.
+    fn call_with_args<P: Serialize, R: DeserializeOwned>(
+        &self,
+        name: &str,
+        args: P,
+        report_args: impl FnOnce() -> Vec<String>,
+    ) -> Result<R> {
+        self.module.call(name, args).map_err(|mut e| {
+            if let Some(e) = e.downcast_mut::<PluginError>() {
+                e.args = report_args();
+            }
+            self.check_error(&e);
+            e
+        })
+    }
+
+    /// Disable the module if the error is caused by the execution budget,
+    /// or if it traps with [`TrapPolicy::Disable`].
+    fn check_error(&self, e: &anyhow::Error) {
+        if let Some(e) = e.downcast_ref::<PluginError>() {
+            if e.is_budget() || self.policy == TrapPolicy::Disable {
+                if !self.disabled.swap(true, Ordering::AcqRel) {
+                    log::error!("{e}, and it is disabled");
+                }
+            } else if e.is_trap() {
+                log::warn!("{e}, and it will be re-instantiated");
+            }
+        }
+    }
+
+    /// Apply the [`TrapPolicy`] to the result of a call.
+    ///
+    /// If the call fails with [`PluginError`] and the policy is not [`TrapPolicy::Fail`],
+    /// the error is logged and [`None`] is returned, so that the caller skips the result.
+    /// The other errors are returned as is.
+    pub fn recover<T>(&self, res: Result<T>) -> Result<Option<T>> {
+        match res {
+            Ok(res) => Ok(Some(res)),
+            Err(e) => match e.downcast_ref::<PluginError>() {
+                Some(plugin_error) if self.policy != TrapPolicy::Fail => {
+                    log::error!("{plugin_error}, and the result is skipped");
+                    Ok(None)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Gets the [`PluginManifest`].
+    pub fn plugin_manifest(&self) -> Result<PluginManifest> {
+        self.call("plugin_manifest", ())
+    }
+
     /// Gets the [`PluginType`].
     pub fn plugin_type(&self) -> Result<PluginType> {
-        self.module.call("plugin_type", ())
+        self.call("plugin_type", ())
     }
 
     /// Processes [`Action`] in action plugin.
     pub fn process_action(&self, ctx: ActionProcessContextRef) -> Result<ActionProcessResult> {
-        self.module.call("process_action", (ctx,))
+        self.call("process_action", (ctx,))
     }
 
     /// Calls a custom command in the text plugin.
@@ -48,7 +135,7 @@ impl<M: RawModule> Module<M> {
         args: &[String],
         ctx: TextProcessContextRef,
     ) -> Result<TextProcessResult> {
-        self.module.call(name, (args, ctx))
+        self.call_with_args(name, (args, ctx), || args.to_vec())
     }
 
     /// Calls a custom command in the line plugin.
@@ -57,22 +144,61 @@ impl<M: RawModule> Module<M> {
         name: &str,
         ctx: LineProcessContextRef,
     ) -> Result<LineProcessResult> {
-        self.module.call(name, (ctx,))
+        let props = ctx.props;
+        self.call_with_args(name, (ctx,), || {
+            props
+                .iter()
+                .map(|(key, value)| format!("{key}: {}", value.get_str()))
+                .collect()
+        })
     }
 
     /// Processes [`Game`] when opening the config file.
     pub fn process_game(&self, ctx: GameProcessContextRef) -> Result<GameProcessResult> {
-        self.module.call("process_game", (ctx,))
+        self.call("process_game", (ctx,))
+    }
+
+    /// Notifies the paragraph plugin when entering a paragraph.
+    pub fn on_paragraph_enter(
+        &self,
+        ctx: ParagraphProcessContextRef,
+    ) -> Result<ParagraphProcessResult> {
+        self.call("on_paragraph_enter", (ctx,))
+    }
+
+    /// Notifies the paragraph plugin when exiting a paragraph.
+    pub fn on_paragraph_exit(
+        &self,
+        ctx: ParagraphProcessContextRef,
+    ) -> Result<ParagraphProcessResult> {
+        self.call("on_paragraph_exit", (ctx,))
+    }
+
+    /// Saves the state of the state plugin.
+    pub fn save_state(&self) -> Result<Vec<u8>> {
+        self.call("save_state", ())
+    }
+
+    /// Loads the state of the state plugin.
+    pub fn load_state(&self, data: &[u8]) -> Result<()> {
+        self.call("load_state", (data,))
     }
 }
 
 /// The plugin runtime.
 pub struct Runtime<M: RawModule + Send + Sync + 'static> {
     modules: HashMap<String, Module<M>>,
+    manifests: HashMap<String, PluginManifest>,
     action_modules: Vec<String>,
     text_modules: HashMap<String, String>,
     line_modules: HashMap<String, String>,
     game_modules: Vec<String>,
+    paragraph_modules: Vec<String>,
+    state_modules: Vec<String>,
+    data_modules: Vec<String>,
+    priorities: HashMap<String, i32>,
+    rng: Arc<Mutex<RngState>>,
+    data_dir: Arc<RwLock<Option<VfsPath>>>,
 }
 
 /// The load status of [`Runtime`].
@@ -85,56 +211,167 @@ pub enum LoadStatus {
 }
 
 impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
-    /// Load plugins from specific directory and plugin names.
+    /// Load plugins from specific directory and plugin names in [`PluginConfig`].
     ///
-    /// The actual load folder will be `rel_to.join(dir)`.
+    /// The actual load folder will be `root_path.join(dir)`.
     ///
-    /// If `names` is empty, all WASM files will be loaded.
+    /// If `modules` is empty, all WASM files will be loaded,
+    /// otherwise a missing plugin is an error.
+    ///
+    /// The plugins are validated by their [`PluginManifest`],
+    /// and sorted so that the dependencies are inserted first.
     #[stream(LoadStatus, lifetime = 'a)]
     pub async fn load<'a>(
-        dir: impl AsRef<str> + 'a,
+        config: &'a PluginConfig,
         root_path: &'a VfsPath,
-        names: &'a [impl AsRef<str>],
         mut store: M::Linker,
     ) -> Result<Arc<Self>> {
-        let path = root_path.join(dir)?;
-        let paths = Self::find_plugins(&path, names)?;
+        let path = root_path.join(&config.dir)?;
+        let paths = Self::find_plugins(&path, &config.modules)?;
 
         yield LoadStatus::CreateEngine;
         let handle = Arc::new(RwLock::new(Weak::new()));
-        log_interop::register(&mut store)?;
-        plugin_interop::register(&mut store, handle.clone())?;
-        fs_interop::register(&mut store, root_path)?;
-        rand_interop::register(&mut store)?;
-        script_interop::register(&mut store)?;
-        let mut runtime = Self::new();
-
-        let total_len = paths.len();
-        for (i, (name, p)) in paths.into_iter().enumerate() {
+        let rng = Arc::new(Mutex::new(RngState::default()));
+        let data_dir = Arc::new(RwLock::new(None));
+        let mut runtime = Self::new(rng.clone(), data_dir.clone());
+
+        // Read all plugins first, so that the linker could prepare them in parallel.
+        let binaries = paths
+            .into_iter()
+            .map(|(name, p)| {
+                let mut buf = vec![];
+                p.open_file()?.read_to_end(&mut buf)?;
+                store.prepare(&buf);
+                Ok((name, buf))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let total_len = binaries.len();
+        let mut modules = vec![];
+        for (i, (name, buf)) in binaries.into_iter().enumerate() {
             yield LoadStatus::LoadPlugin(name.clone(), i, total_len);
-            let mut buf = vec![];
-            p.open_file()?.read_to_end(&mut buf)?;
-            let module = Module::new(store.create(&buf)?);
-            runtime.insert_module(name, module)?;
+            let ModuleConfig {
+                config: module_config,
+                limits,
+                capabilities,
+                on_trap,
+                ..
+            } = config.module(&name);
+            let data = (capabilities.fs && capabilities.data).then(|| {
+                runtime.data_modules.push(name.clone());
+                fs_interop::DataDir::new(data_dir.clone(), &name)
+            });
+            Self::register_imports(&mut store, &capabilities, root_path, data, &handle, &rng)?;
+            store.set_limits(limits);
+            let raw_module = store.create(&name, &buf).map_err(|e| {
+                if capabilities == PluginCapabilities::default() {
+                    anyhow!("Cannot create plugin \"{name}\": {e}")
+                } else {
+                    anyhow!("Cannot create plugin \"{name}\", maybe a capability is required: {e}")
+                }
+            })?;
+            let module = Module::new(raw_module, module_config, on_trap);
+            let manifest = check_manifest(&name, &module)?;
+            modules.push(Some((name, module, manifest)));
         }
+        let order = sort_plugins(
+            modules
+                .iter()
+                .flatten()
+                .map(|(name, _, manifest)| (name.as_str(), manifest)),
+        )?;
+        for i in order {
+            let (name, module, manifest) = modules[i].take().expect("the order is a permutation");
+            runtime.insert_module(name, module, manifest)?;
+        }
+        runtime.action_modules =
+            runtime.resolve_pipeline(&runtime.action_modules, &config.pipeline.action, "action")?;
+        runtime.game_modules =
+            runtime.resolve_pipeline(&runtime.game_modules, &config.pipeline.game, "game")?;
         let runtime = Arc::new(runtime);
         *handle.write().unwrap() = Arc::downgrade(&runtime);
         Ok(runtime)
     }
 
-    fn new() -> Self {
+    /// Link the imports allowed by the capabilities.
+    fn register_imports(
+        store: &mut M::Linker,
+        capabilities: &PluginCapabilities,
+        root_path: &VfsPath,
+        data: Option<fs_interop::DataDir>,
+        handle: &Arc<RwLock<Weak<Self>>>,
+        rng: &Arc<Mutex<RngState>>,
+    ) -> Result<()> {
+        store.clear_imports();
+        log_interop::register(store)?;
+        if capabilities.plugin {
+            plugin_interop::register(store, handle.clone())?;
+        }
+        if capabilities.fs {
+            fs_interop::register(store, root_path, capabilities.dirs.as_deref(), data)?;
+        }
+        if capabilities.rand {
+            rand_interop::register(store, rng.clone())?;
+        }
+        if capabilities.script {
+            script_interop::register(store)?;
+        }
+        Ok(())
+    }
+
+    fn new(rng: Arc<Mutex<RngState>>, data_dir: Arc<RwLock<Option<VfsPath>>>) -> Self {
         Self {
             modules: HashMap::default(),
+            manifests: HashMap::default(),
             action_modules: vec![],
             text_modules: HashMap::default(),
             line_modules: HashMap::default(),
             game_modules: vec![],
+            paragraph_modules: vec![],
+            state_modules: vec![],
+            data_modules: vec![],
+            priorities: HashMap::default(),
+            rng,
+            data_dir,
         }
     }
 
-    fn find_plugins(path: &VfsPath, names: &[impl AsRef<str>]) -> Result<Vec<(String, VfsPath)>> {
+    /// The current state of the random number generator.
+    pub fn rng_state(&self) -> RngState {
+        *self.rng.lock().unwrap()
+    }
+
+    /// Set the state of the random number generator.
+    pub fn set_rng_state(&self, state: RngState) {
+        *self.rng.lock().unwrap() = state;
+    }
+
+    /// The writable data directory of the plugins.
+    pub fn data_dir(&self) -> Option<VfsPath> {
+        self.data_dir.read().unwrap().clone()
+    }
+
+    /// Set the writable data directory of the plugins.
+    ///
+    /// Every plugin could write to a sub directory named after itself.
+    /// If it is [`None`], the plugins could not write any files.
+    ///
+    /// The sub directories are created here once.
+    pub fn set_data_dir(&self, path: Option<VfsPath>) {
+        if let Some(path) = &path {
+            for name in &self.data_modules {
+                if let Err(e) = path.join(name).and_then(|dir| dir.create_dir_all()) {
+                    log::warn!("Cannot create the data directory of plugin \"{name}\": {e}");
+                }
+            }
+        }
+        *self.data_dir.write().unwrap() = path;
+    }
+
+    fn find_plugins(path: &VfsPath, names: &[ModuleConfig]) -> Result<Vec<(String, VfsPath)>> {
         let paths = if names.is_empty() {
-            path.read_dir()?
+            let mut paths = path
+                .read_dir()?
                 .filter_map(|p| {
                     if p.is_file().unwrap_or_default()
                         && p.extension().unwrap_or_default() == "wasm"
@@ -149,25 +386,33 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
                         None
                     }
                 })
-                .collect::<Vec<_>>()
+                .collect::<Vec<_>>();
+            // The order of `read_dir` is not specified.
+            paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+            paths
         } else {
             names
                 .iter()
-                .filter_map(|name| {
-                    let name = name.as_ref();
-                    let p = path.join(format!("{name}.wasm")).ok()?;
-                    if p.exists().unwrap_or_default() {
-                        Some((name.to_string(), p))
+                .map(|name| {
+                    let name = name.name.as_str();
+                    let p = path.join(format!("{name}.wasm"))?;
+                    if p.exists()? {
+                        Ok((name.to_string(), p))
                     } else {
-                        None
+                        Err(anyhow!("Cannot find plugin \"{name}\""))
                     }
                 })
-                .collect::<Vec<_>>()
+                .collect::<Result<Vec<_>>>()?
         };
         Ok(paths)
     }
 
-    fn insert_module(&mut self, name: String, module: Module<M>) -> Result<()> {
+    fn insert_module(
+        &mut self,
+        name: String,
+        module: Module<M>,
+        manifest: PluginManifest,
+    ) -> Result<()> {
         let plugin_type =
             unwrap_or_default_log!(module.plugin_type(), "Cannot determine module type");
         if plugin_type.action {
@@ -188,13 +433,55 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
         if plugin_type.game {
             self.game_modules.push(name.clone());
         }
+        if plugin_type.paragraph {
+            self.paragraph_modules.push(name.clone());
+        }
+        if plugin_type.state {
+            self.state_modules.push(name.clone());
+        }
+        self.priorities.insert(name.clone(), plugin_type.priority);
+        self.manifests.insert(name.clone(), manifest);
         self.modules.insert(name, module);
         Ok(())
     }
 
+    /// Resolve the order of a pipeline.
+    ///
+    /// The explicit names run first, in the specified order.
+    /// The others are sorted by their priorities,
+    /// and keep the dependency order if the priorities are the same.
+    /// It fails if a plugin runs before its dependency in the resolved order.
+    fn resolve_pipeline(
+        &self,
+        modules: &[String],
+        explicit: &[String],
+        kind: &str,
+    ) -> Result<Vec<String>> {
+        for name in explicit {
+            if !modules.contains(name) {
+                bail!("Plugin \"{name}\" in the {kind} pipeline is not a loaded {kind} plugin");
+            }
+        }
+        let mut rest = modules
+            .iter()
+            .filter(|name| !explicit.contains(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        rest.sort_by_key(|name| self.priorities.get(name).copied().unwrap_or_default());
+        let pipeline = explicit.iter().cloned().chain(rest).collect::<Vec<_>>();
+        check_pipeline(&pipeline, &self.manifests, kind)?;
+        Ok(pipeline)
+    }
+
     /// Gets module from name.
+    /// The disabled modules are skipped.
     pub fn module(&self, key: &str) -> Option<&Module<M>> {
-        self.modules.get(key)
+        self.modules.get(key).filter(|m| !m.is_disabled())
+    }
+
+    /// Gets the [`PluginManifest`] of a module.
+    pub fn manifest(&self, key: &str) -> Option<&PluginManifest> {
+        self.manifests.get(key)
     }
 
     /// Iterates action modules.
@@ -214,8 +501,147 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
         self.line_modules.get(cmd).and_then(|key| self.module(key))
     }
 
+    /// The names of action modules, in the order they run.
+    pub fn action_pipeline(&self) -> &[String] {
+        &self.action_modules
+    }
+
+    /// The names of game modules, in the order they run.
+    pub fn game_pipeline(&self) -> &[String] {
+        &self.game_modules
+    }
+
     /// Iterates game modules.
     pub fn game_modules(&self) -> impl Iterator<Item = &Module<M>> {
         self.game_modules.iter().filter_map(|key| self.module(key))
     }
+
+    /// Saves the states of state modules, keyed by the module names.
+    /// The modules failed to save are skipped.
+    pub fn save_states(&self) -> HashMap<String, Vec<u8>> {
+        let mut states = HashMap::new();
+        for key in &self.state_modules {
+            if let Some(module) = self.module(key) {
+                match module.save_state() {
+                    Ok(data) => {
+                        states.insert(key.clone(), data);
+                    }
+                    Err(e) => log::error!("Cannot save state of \"{key}\": {e}"),
+                }
+            }
+        }
+        states
+    }
+
+    /// Loads the states of state modules.
+    /// The states of unknown modules are ignored,
+    /// and the state modules not in `states` load empty data to reset.
+    pub fn load_states(&self, states: &HashMap<String, Vec<u8>>) {
+        for key in &self.state_modules {
+            if let Some(module) = self.module(key) {
+                let data = states
+                    .get(key)
+                    .map(|data| data.as_slice())
+                    .unwrap_or_default();
+                if let Err(e) = module.load_state(data) {
+                    log::error!("Cannot load state of \"{key}\": {e}");
+                }
+            }
+        }
+    }
+
+    /// Iterates paragraph modules.
+    pub fn paragraph_modules(&self) -> impl Iterator<Item = &Module<M>> {
+        self.paragraph_modules
+            .iter()
+            .filter_map(|key| self.module(key))
+    }
+}
+
+fn check_manifest<M: RawModule>(name: &str, module: &Module<M>) -> Result<PluginManifest> {
+    let manifest = module
+        .plugin_manifest()
+        .map_err(|e| anyhow!("Cannot get the manifest of plugin \"{name}\": {e}"))?;
+    if manifest.abi_version != ABI_VERSION {
+        bail!(
+            "Plugin \"{}\" {} requires ABI version {}, but the runtime provides {}",
+            name,
+            manifest.version,
+            manifest.abi_version,
+            ABI_VERSION
+        );
+    }
+    if manifest.name != name {
+        log::warn!(
+            "Plugin \"{}\" is loaded from file \"{}\"",
+            manifest.name,
+            name
+        );
+    }
+    Ok(manifest)
+}
+
+/// Sort the plugins so that the dependencies come first.
+/// The independent plugins keep the load order.
+fn sort_plugins<'a>(
+    manifests: impl IntoIterator<Item = (&'a str, &'a PluginManifest)>,
+) -> Result<Vec<usize>> {
+    let manifests = manifests.into_iter().collect::<Vec<_>>();
+    let index = manifests
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (*name, i))
+        .collect::<HashMap<_, _>>();
+    // The count of unresolved dependencies, and the dependents of each plugin.
+    let mut degrees = vec![0usize; manifests.len()];
+    let mut dependents = vec![vec![]; manifests.len()];
+    for (i, (name, manifest)) in manifests.iter().enumerate() {
+        for dep in &manifest.dependencies {
+            let Some(&j) = index.get(dep.as_str()) else {
+                bail!("Plugin \"{name}\" depends on \"{dep}\", which is not loaded");
+            };
+            degrees[i] += 1;
+            dependents[j].push(i);
+        }
+    }
+    let mut ready = BTreeSet::from_iter((0..manifests.len()).filter(|&i| degrees[i] == 0));
+    let mut order = vec![];
+    while let Some(i) = ready.pop_first() {
+        order.push(i);
+        for &j in &dependents[i] {
+            degrees[j] -= 1;
+            if degrees[j] == 0 {
+                ready.insert(j);
+            }
+        }
+    }
+    if order.len() < manifests.len() {
+        let cycle = (0..manifests.len())
+            .filter(|&i| degrees[i] > 0)
+            .map(|i| manifests[i].0)
+            .collect::<Vec<_>>();
+        bail!("Circular dependencies among plugins: {}", cycle.join(", "));
+    }
+    Ok(order)
+}
+
+/// Check that every plugin runs after its dependencies in the pipeline.
+fn check_pipeline(
+    pipeline: &[String],
+    manifests: &HashMap<String, PluginManifest>,
+    kind: &str,
+) -> Result<()> {
+    for (i, name) in pipeline.iter().enumerate() {
+        let Some(manifest) = manifests.get(name) else {
+            continue;
+        };
+        for dep in &manifest.dependencies {
+            if pipeline[i + 1..].contains(dep) {
+                bail!(
+                    "Plugin \"{name}\" runs before its dependency \"{dep}\" in the {kind} pipeline"
+                );
+            }
+        }
+    }
+    Ok(())
 }
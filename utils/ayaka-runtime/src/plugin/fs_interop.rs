@@ -1,11 +1,11 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ayaka_bindings_types::{FileMetadata, FileSeekFrom};
 use ayaka_plugin::{Linker, LinkerHandle, RawModule};
 use slab::Slab;
 use std::{
     collections::HashMap,
-    io::SeekFrom,
-    sync::{Arc, Mutex},
+    io::{SeekFrom, Write},
+    sync::{Arc, Mutex, RwLock},
 };
 use vfs::*;
 
@@ -38,27 +38,207 @@ impl FDMap {
     }
 }
 
-pub fn register<M: RawModule>(store: &mut impl Linker<M>, root_path: &VfsPath) -> Result<()> {
-    let p = root_path.clone();
+/// An opened file in the data directory.
+enum DataFile {
+    Read(Box<dyn SeekAndRead + Send>),
+    Write(Box<dyn SeekAndWrite + Send>),
+}
+
+#[derive(Default)]
+struct DataFDMap {
+    map: Slab<DataFile>,
+}
+
+impl DataFDMap {
+    pub fn open(&mut self, file: DataFile) -> u64 {
+        self.map.insert(file) as u64
+    }
+
+    pub fn close(&mut self, fd: u64) {
+        self.map.try_remove(fd as usize);
+    }
+
+    fn get(&mut self, fd: u64) -> std::io::Result<&mut DataFile> {
+        self.map
+            .get_mut(fd as usize)
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    pub fn read(&mut self, fd: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.get(fd)? {
+            DataFile::Read(file) => file.read(buf),
+            DataFile::Write(_) => Err(std::io::ErrorKind::Unsupported.into()),
+        }
+    }
+
+    pub fn write(&mut self, fd: u64, buf: &[u8]) -> std::io::Result<usize> {
+        match self.get(fd)? {
+            DataFile::Read(_) => Err(std::io::ErrorKind::Unsupported.into()),
+            DataFile::Write(file) => file.write(buf),
+        }
+    }
+
+    pub fn flush(&mut self, fd: u64) -> std::io::Result<()> {
+        match self.get(fd)? {
+            DataFile::Read(_) => Ok(()),
+            DataFile::Write(file) => file.flush(),
+        }
+    }
+
+    pub fn seek(&mut self, fd: u64, pos: SeekFrom) -> std::io::Result<u64> {
+        match self.get(fd)? {
+            DataFile::Read(file) => file.seek(pos),
+            DataFile::Write(file) => file.seek(pos),
+        }
+    }
+}
+
+/// The root of plugin filesystem, with allowed directories.
+#[derive(Clone)]
+struct Sandbox {
+    root: VfsPath,
+    dirs: Option<Arc<Vec<Vec<String>>>>,
+}
+
+impl Sandbox {
+    fn new(root: &VfsPath, dirs: Option<&[String]>) -> Result<Self> {
+        let dirs = dirs
+            .map(|dirs| {
+                dirs.iter()
+                    .map(|dir| {
+                        let components = Self::components(dir)
+                            .ok_or_else(|| anyhow!("Invalid allowed directory: {dir}"))?;
+                        Ok(components.into_iter().map(String::from).collect())
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .map(Arc::new);
+        Ok(Self {
+            root: root.clone(),
+            dirs,
+        })
+    }
+
+    /// Split the path, and reject `..` components.
+    fn components(path: &str) -> Option<Vec<&str>> {
+        let mut components = vec![];
+        for c in path.split('/') {
+            match c {
+                "" | "." => {}
+                ".." => return None,
+                c => components.push(c),
+            }
+        }
+        Some(components)
+    }
+
+    /// Resolve the path from the plugin.
+    /// It returns [`None`] if the path is out of the allowed directories.
+    fn resolve(&self, path: &str) -> Option<VfsPath> {
+        let Some(components) = Self::components(path) else {
+            log::warn!("Plugin tries to access path out of root: {path}");
+            return None;
+        };
+        if let Some(dirs) = &self.dirs {
+            let allowed = dirs.iter().any(|dir| {
+                dir.len() <= components.len() && dir.iter().zip(&components).all(|(a, b)| a == b)
+            });
+            if !allowed {
+                log::warn!("Plugin tries to access path not allowed: {path}");
+                return None;
+            }
+        }
+        if components.is_empty() {
+            Some(self.root.clone())
+        } else {
+            self.root.join(components.join("/")).ok()
+        }
+    }
+}
+
+/// The writable data directory of a plugin.
+///
+/// It is a sub directory named after the plugin,
+/// under the data directory set to the runtime.
+#[derive(Clone)]
+pub struct DataDir {
+    root: Arc<RwLock<Option<VfsPath>>>,
+    name: String,
+}
+
+impl DataDir {
+    pub fn new(root: Arc<RwLock<Option<VfsPath>>>, name: impl Into<String>) -> Self {
+        Self {
+            root,
+            name: name.into(),
+        }
+    }
+
+    /// Resolve the path from the plugin.
+    /// It returns [`None`] if the data directory is not set.
+    ///
+    /// The directory of the plugin is created by [`crate::Runtime::set_data_dir`],
+    /// so resolving a path has no side effect.
+    fn resolve(&self, path: &str) -> Option<VfsPath> {
+        let Some(components) = Sandbox::components(path) else {
+            log::warn!("Plugin tries to access path out of data directory: {path}");
+            return None;
+        };
+        let dir = self.root.read().unwrap().as_ref()?.join(&self.name).ok()?;
+        if components.is_empty() {
+            Some(dir)
+        } else {
+            dir.join(components.join("/")).ok()
+        }
+    }
+
+    /// Read the directory from the plugin.
+    /// The entries are full paths relative to the directory of the plugin,
+    /// the same as the entries of the root `__read_dir`.
+    fn read_dir(&self, path: &str) -> Option<Vec<String>> {
+        let base = self.resolve("/")?.as_str().len();
+        let iter = self.resolve(path)?.read_dir().ok()?;
+        Some(iter.map(|p| p.as_str()[base..].to_string()).collect())
+    }
+}
+
+/// Register the `fs` imports.
+/// The paths out of `dirs` are treated as not found.
+///
+/// The data functions operate in the `data` directory.
+/// If it is [`None`], they fail as the directory is not set.
+pub fn register<M: RawModule>(
+    store: &mut impl Linker<M>,
+    root_path: &VfsPath,
+    dirs: Option<&[String]>,
+    data: Option<DataDir>,
+) -> Result<()> {
+    let sandbox = Sandbox::new(root_path, dirs)?;
+    let p = sandbox.clone();
     let read_dir_func = store.wrap(move |(path,): (String,)| {
-        Ok(p.join(&path[1..])?
-            .read_dir()
-            .map(|iter| iter.map(|p| p.as_str().to_string()).collect::<Vec<_>>())
-            .ok())
+        Ok(p.resolve(&path)
+            .and_then(|p| p.read_dir().ok())
+            .map(|iter| iter.map(|p| p.as_str().to_string()).collect::<Vec<_>>()))
     });
-    let p = root_path.clone();
+    let p = sandbox.clone();
     let metadata_func = store.wrap(move |(path,): (String,)| {
-        Ok(p.join(&path[1..])?.metadata().map(FileMetadata::from).ok())
+        Ok(p.resolve(&path)
+            .and_then(|p| p.metadata().ok())
+            .map(FileMetadata::from))
+    });
+    let p = sandbox.clone();
+    let exists_func = store.wrap(move |(path,): (String,)| match p.resolve(&path) {
+        Some(p) => Ok(p.exists()?),
+        None => Ok(false),
     });
-    let p = root_path.clone();
-    let exists_func = store.wrap(move |(path,): (String,)| Ok(p.join(&path[1..])?.exists()?));
 
     let fd_map = Arc::new(Mutex::new(FDMap::default()));
-    let p = root_path.clone();
+    let p = sandbox;
     let map = fd_map.clone();
     let open_file_func = store.wrap(move |(path,): (String,)| {
-        let file = p.join(&path[1..])?.open_file();
-        Ok(file.map(|file| map.lock().unwrap().open(file)).ok())
+        let file = p.resolve(&path).and_then(|p| p.open_file().ok());
+        Ok(file.map(|file| map.lock().unwrap().open(file)))
     });
     let map = fd_map.clone();
     let close_file_func = store.wrap(move |(fd,): (u64,)| {
@@ -68,24 +248,202 @@ pub fn register<M: RawModule>(store: &mut impl Linker<M>, root_path: &VfsPath) -
     let map = fd_map.clone();
     let file_read_func = store.wrap_with(move |mut handle, (fd, ptr, len): (u64, i32, i32)| {
         Ok(handle
-            .slice_mut(ptr, len, |buf| map.lock().unwrap().read(fd, buf))
+            .slice_mut(ptr, len, |buf| map.lock().unwrap().read(fd, buf))?
             .ok())
     });
     let map = fd_map;
     let file_seek_func = store.wrap(move |(fd, pos): (u64, FileSeekFrom)| {
         Ok(map.lock().unwrap().seek(fd, pos.into()).ok())
     });
-    store.import(
-        "fs",
-        HashMap::from([
-            ("__read_dir".to_string(), read_dir_func),
-            ("__metadata".to_string(), metadata_func),
-            ("__exists".to_string(), exists_func),
-            ("__open_file".to_string(), open_file_func),
-            ("__close_file".to_string(), close_file_func),
-            ("__file_read".to_string(), file_read_func),
-            ("__file_seek".to_string(), file_seek_func),
-        ]),
-    )?;
+    let mut funcs = HashMap::from([
+        ("__read_dir".to_string(), read_dir_func),
+        ("__metadata".to_string(), metadata_func),
+        ("__exists".to_string(), exists_func),
+        ("__open_file".to_string(), open_file_func),
+        ("__close_file".to_string(), close_file_func),
+        ("__file_read".to_string(), file_read_func),
+        ("__file_seek".to_string(), file_seek_func),
+    ]);
+    funcs.extend(data_funcs(store, data));
+    store.import("fs", funcs)?;
     Ok(())
 }
+
+fn data_funcs<M: RawModule>(
+    store: &impl Linker<M>,
+    data: Option<DataDir>,
+) -> HashMap<String, M::Func> {
+    let d = data.clone();
+    let read_dir_func =
+        store.wrap(move |(path,): (String,)| Ok(d.as_ref().and_then(|data| data.read_dir(&path))));
+    let resolve = move |path: &str| data.as_ref().and_then(|data| data.resolve(path));
+    let p = resolve.clone();
+    let metadata_func = store.wrap(move |(path,): (String,)| {
+        Ok(p(&path)
+            .and_then(|p| p.metadata().ok())
+            .map(FileMetadata::from))
+    });
+    let p = resolve.clone();
+    let exists_func = store.wrap(move |(path,): (String,)| {
+        Ok(p(&path)
+            .map(|p| p.exists().unwrap_or_default())
+            .unwrap_or_default())
+    });
+    let p = resolve.clone();
+    let create_dir_func = store.wrap(move |(path,): (String,)| {
+        Ok(p(&path)
+            .map(|p| p.create_dir_all().is_ok())
+            .unwrap_or_default())
+    });
+    let p = resolve.clone();
+    let remove_file_func = store.wrap(move |(path,): (String,)| {
+        Ok(p(&path)
+            .map(|p| p.remove_file().is_ok())
+            .unwrap_or_default())
+    });
+    let p = resolve.clone();
+    let remove_dir_func = store.wrap(move |(path,): (String,)| {
+        Ok(p(&path)
+            .map(|p| p.remove_dir_all().is_ok())
+            .unwrap_or_default())
+    });
+
+    let fd_map = Arc::new(Mutex::new(DataFDMap::default()));
+    let p = resolve.clone();
+    let map = fd_map.clone();
+    let open_file_func = store.wrap(move |(path,): (String,)| {
+        let file = p(&path).and_then(|p| p.open_file().ok());
+        Ok(file.map(|file| map.lock().unwrap().open(DataFile::Read(file))))
+    });
+    let p = resolve.clone();
+    let map = fd_map.clone();
+    let create_file_func = store.wrap(move |(path,): (String,)| {
+        let file = p(&path).and_then(|p| p.create_file().ok());
+        Ok(file.map(|file| map.lock().unwrap().open(DataFile::Write(file))))
+    });
+    let p = resolve;
+    let map = fd_map.clone();
+    let append_file_func = store.wrap(move |(path,): (String,)| {
+        let file = p(&path).and_then(|p| {
+            if p.exists().unwrap_or_default() {
+                p.append_file().ok()
+            } else {
+                p.create_file().ok()
+            }
+        });
+        Ok(file.map(|file| map.lock().unwrap().open(DataFile::Write(file))))
+    });
+    let map = fd_map.clone();
+    let close_file_func = store.wrap(move |(fd,): (u64,)| {
+        map.lock().unwrap().close(fd);
+        Ok(())
+    });
+    let map = fd_map.clone();
+    let file_read_func = store.wrap_with(move |mut handle, (fd, ptr, len): (u64, i32, i32)| {
+        Ok(handle
+            .slice_mut(ptr, len, |buf| map.lock().unwrap().read(fd, buf))?
+            .ok())
+    });
+    let map = fd_map.clone();
+    let file_write_func = store.wrap_with(move |handle, (fd, ptr, len): (u64, i32, i32)| {
+        Ok(handle
+            .slice(ptr, len, |buf| map.lock().unwrap().write(fd, buf))?
+            .ok())
+    });
+    let map = fd_map.clone();
+    let file_flush_func =
+        store.wrap(move |(fd,): (u64,)| Ok(map.lock().unwrap().flush(fd).is_ok()));
+    let map = fd_map;
+    let file_seek_func = store.wrap(move |(fd, pos): (u64, FileSeekFrom)| {
+        Ok(map.lock().unwrap().seek(fd, pos.into()).ok())
+    });
+    HashMap::from([
+        ("__data_read_dir".to_string(), read_dir_func),
+        ("__data_metadata".to_string(), metadata_func),
+        ("__data_exists".to_string(), exists_func),
+        ("__data_create_dir".to_string(), create_dir_func),
+        ("__data_remove_file".to_string(), remove_file_func),
+        ("__data_remove_dir".to_string(), remove_dir_func),
+        ("__data_open_file".to_string(), open_file_func),
+        ("__data_create_file".to_string(), create_file_func),
+        ("__data_append_file".to_string(), append_file_func),
+        ("__data_close_file".to_string(), close_file_func),
+        ("__data_file_read".to_string(), file_read_func),
+        ("__data_file_write".to_string(), file_write_func),
+        ("__data_file_flush".to_string(), file_flush_func),
+        ("__data_file_seek".to_string(), file_seek_func),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DataDir, Sandbox};
+    use std::sync::{Arc, RwLock};
+    use vfs::{MemoryFS, VfsPath};
+
+    fn sandbox(dirs: Option<&[String]>) -> Sandbox {
+        let root: VfsPath = MemoryFS::new().into();
+        root.join("bg").unwrap().create_dir().unwrap();
+        root.join("bgm").unwrap().create_dir().unwrap();
+        root.join("bg/home.png").unwrap().create_file().unwrap();
+        Sandbox::new(&root, dirs).unwrap()
+    }
+
+    #[test]
+    fn escape() {
+        let sandbox = sandbox(None);
+        assert!(sandbox.resolve("/bg/home.png").is_some());
+        assert!(sandbox.resolve("/").is_some());
+        assert!(sandbox.resolve("/../config.yaml").is_none());
+        assert!(sandbox.resolve("/bg/../../config.yaml").is_none());
+    }
+
+    #[test]
+    fn dirs() {
+        let sandbox = sandbox(Some(&["bg".to_string()]));
+        assert!(sandbox.resolve("/bg/home.png").is_some());
+        assert!(sandbox.resolve("/./bg/home.png").is_some());
+        assert!(sandbox.resolve("/bgm").is_none());
+        assert!(sandbox.resolve("/").is_none());
+    }
+
+    #[test]
+    fn invalid_dirs() {
+        let root: VfsPath = MemoryFS::new().into();
+        assert!(Sandbox::new(&root, Some(&["../bg".to_string()])).is_err());
+    }
+
+    #[test]
+    fn data_dir() {
+        let root: VfsPath = MemoryFS::new().into();
+        let dir = Arc::new(RwLock::new(None));
+        let data = DataDir::new(dir.clone(), "scope");
+        assert!(data.resolve("/cache.txt").is_none());
+
+        *dir.write().unwrap() = Some(root.clone());
+        let file = data.resolve("/cache.txt").unwrap();
+        assert_eq!(file.as_str(), "/scope/cache.txt");
+        assert!(!root.join("scope").unwrap().exists().unwrap());
+        assert!(data.resolve("/../media/cache.txt").is_none());
+    }
+
+    #[test]
+    fn data_read_dir() {
+        let root: VfsPath = MemoryFS::new().into();
+        let data_root = root.join("data").unwrap();
+        data_root
+            .join("scope/saves")
+            .unwrap()
+            .create_dir_all()
+            .unwrap();
+        data_root
+            .join("scope/saves/1.bin")
+            .unwrap()
+            .create_file()
+            .unwrap();
+        let data = DataDir::new(Arc::new(RwLock::new(Some(data_root))), "scope");
+        assert_eq!(data.read_dir("/").unwrap(), ["/saves"]);
+        assert_eq!(data.read_dir("/saves").unwrap(), ["/saves/1.bin"]);
+        assert!(data.read_dir("/cache").is_none());
+    }
+}
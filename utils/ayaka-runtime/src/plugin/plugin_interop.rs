@@ -22,12 +22,14 @@ pub fn register<M: RawModule + Send + Sync + 'static>(
     let call_func = store.wrap_with(
         move |mut handle, (module, name, args): (String, String, Vec<u8>)| {
             if let Some(this) = h.read().unwrap().upgrade() {
-                Ok(handle.call(
-                    this.modules[&module].module.inner(),
-                    &name,
-                    &args,
-                    |slice| Ok(slice.to_vec()),
-                )?)
+                let Some(m) = this.module(&module) else {
+                    bail!("Plugin \"{module}\" is not loaded or disabled.")
+                };
+                let res = handle.call(m.module.inner(), &name, &args, |slice| Ok(slice.to_vec()));
+                if let Err(e) = &res {
+                    m.check_error(e);
+                }
+                res
             } else {
                 bail!("Runtime hasn't been initialized.")
             }
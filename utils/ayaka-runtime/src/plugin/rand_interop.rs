@@ -1,19 +1,49 @@
 use anyhow::{anyhow, Result};
+use ayaka_bindings_types::RngState;
 use ayaka_plugin::{Linker, RawModule};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::{
     collections::HashMap,
-    sync::{LazyLock, Mutex},
+    sync::{Arc, Mutex},
 };
 
-static RNG: LazyLock<Mutex<StdRng>> = LazyLock::new(|| Mutex::new(StdRng::from_os_rng()));
+/// The seed of the `count`-th number.
+///
+/// The count is mixed like SplitMix64,
+/// so that the numbers of consecutive seeds are not shifted copies of each other.
+fn rng_seed(state: &RngState) -> u64 {
+    state.seed ^ state.count.wrapping_mul(0x9E3779B97F4A7C15)
+}
 
-pub fn register<M: RawModule>(store: &mut impl Linker<M>) -> Result<()> {
-    let rnd_func = store.wrap(|(start, end): (i64, i64)| {
-        RNG.lock()
-            .map(|mut rng| rng.random_range(start..end))
-            .map_err(|_| anyhow!("Cannot lock random engine"))
+pub fn register<M: RawModule>(
+    store: &mut impl Linker<M>,
+    state: Arc<Mutex<RngState>>,
+) -> Result<()> {
+    let rnd_func = store.wrap(move |(start, end): (i64, i64)| {
+        let mut state = state
+            .lock()
+            .map_err(|_| anyhow!("Cannot lock random engine"))?;
+        let mut rng = StdRng::seed_from_u64(rng_seed(&state));
+        state.count += 1;
+        Ok(rng.random_range(start..end))
     });
     store.import("rand", HashMap::from([("__rnd".to_string(), rnd_func)]))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::rng_seed;
+    use ayaka_bindings_types::RngState;
+
+    #[test]
+    fn consecutive_seeds() {
+        let seeds = (0..4)
+            .map(|count| rng_seed(&RngState { seed: 42, count }))
+            .collect::<Vec<_>>();
+        let next_seeds = (0..4)
+            .map(|count| rng_seed(&RngState { seed: 43, count }))
+            .collect::<Vec<_>>();
+        assert!(next_seeds.iter().all(|seed| !seeds.contains(seed)));
+    }
+}
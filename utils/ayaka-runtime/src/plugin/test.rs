@@ -24,22 +24,54 @@ impl<'a, M: RawModule> ModuleWrapper<'a, M> {
             frontend,
             ctx: &ctx,
             props: &props,
+            config: self.module.config(),
         };
         let res = self.module.dispatch_line("exec", ctx).unwrap();
         res.locals
     }
 }
 
-async fn with_ctx<M: RawModule + Send + Sync + 'static>(f: impl FnOnce(&ModuleWrapper<M>))
+async fn load<M: RawModule + Send + Sync + 'static>(
+    linker_config: LinkerConfig,
+) -> anyhow::Result<Arc<Runtime<M>>>
+where
+    M::Linker: Linker<M, Config = LinkerConfig>,
+{
+    load_modules(linker_config, vec!["ayacript".into(), "random".into()]).await
+}
+
+async fn load_modules<M: RawModule + Send + Sync + 'static>(
+    linker_config: LinkerConfig,
+    modules: Vec<ModuleConfig>,
+) -> anyhow::Result<Arc<Runtime<M>>>
 where
-    M::Linker: Linker<M, Config = ()>,
+    M::Linker: Linker<M, Config = LinkerConfig>,
 {
     let root_path =
         vfs::PhysicalFS::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples")).into();
-    let linker = M::Linker::new(()).unwrap();
-    let runtime = Runtime::load("plugins", &root_path, &["ayacript", "random"], linker)
-        .await
-        .unwrap();
+    let linker = M::Linker::new(linker_config)?;
+    let config = PluginConfig {
+        dir: "plugins".to_string(),
+        modules,
+        ..Default::default()
+    };
+    Runtime::load(&config, &root_path, linker).await
+}
+
+async fn with_ctx<M: RawModule + Send + Sync + 'static>(f: impl FnOnce(&ModuleWrapper<M>))
+where
+    M::Linker: Linker<M, Config = LinkerConfig>,
+{
+    with_ctx_config(LinkerConfig::default(), f).await
+}
+
+async fn with_ctx_config<M: RawModule + Send + Sync + 'static>(
+    linker_config: LinkerConfig,
+    f: impl FnOnce(&ModuleWrapper<M>),
+) where
+    M::Linker: Linker<M, Config = LinkerConfig>,
+{
+    let runtime = load::<M>(linker_config).await.unwrap();
     let module = runtime.line_module("exec").unwrap();
     let wrapper = ModuleWrapper { module };
     f(&wrapper);
@@ -53,7 +85,7 @@ mod runtime_tests {
     #[tokio::test]
     async fn vars<M: RawModule + Send + Sync + 'static>()
     where
-        M::Linker: Linker<M, Config = ()>,
+        M::Linker: Linker<M, Config = LinkerConfig>,
     {
         with_ctx::<M>(|ctx| {
             assert_eq!(
@@ -85,7 +117,7 @@ mod runtime_tests {
     #[tokio::test]
     async fn if_test<M: RawModule + Send + Sync + 'static>()
     where
-        M::Linker: Linker<M, Config = ()>,
+        M::Linker: Linker<M, Config = LinkerConfig>,
     {
         with_ctx::<M>(|ctx| {
             assert_eq!(
@@ -113,7 +145,7 @@ mod runtime_tests {
     #[tokio::test]
     async fn random<M: RawModule + Send + Sync + 'static>()
     where
-        M::Linker: Linker<M, Config = ()>,
+        M::Linker: Linker<M, Config = LinkerConfig>,
     {
         with_ctx::<M>(|ctx| {
             assert!((0..10).contains(
@@ -128,8 +160,179 @@ mod runtime_tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn fuel<M: RawModule + Send + Sync + 'static>()
+    where
+        M::Linker: Linker<M, Config = LinkerConfig>,
+    {
+        let config = LinkerConfig {
+            fuel: Some(1_000_000_000),
+            ..Default::default()
+        };
+        with_ctx_config::<M>(config, |ctx| {
+            assert_eq!(ctx.call("$a = 1 + 1")["a"], RawValue::Num(2));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn out_of_fuel<M: RawModule + Send + Sync + 'static>()
+    where
+        M::Linker: Linker<M, Config = LinkerConfig>,
+    {
+        let config = LinkerConfig {
+            fuel: Some(1),
+            ..Default::default()
+        };
+        assert!(load::<M>(config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn memory<M: RawModule + Send + Sync + 'static>()
+    where
+        M::Linker: Linker<M, Config = LinkerConfig>,
+    {
+        let config = LinkerConfig {
+            memory: Some(256 * 1024 * 1024),
+            ..Default::default()
+        };
+        with_ctx_config::<M>(config, |ctx| {
+            assert_eq!(ctx.call("$a = 1 + 1")["a"], RawValue::Num(2));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn out_of_memory<M: RawModule + Send + Sync + 'static>()
+    where
+        M::Linker: Linker<M, Config = LinkerConfig>,
+    {
+        // Less than the initial memory of the plugins.
+        let config = LinkerConfig {
+            memory: Some(64 * 1024),
+            ..Default::default()
+        };
+        let err = load::<M>(config).await.err().unwrap();
+        assert!(err.to_string().contains("\"ayacript\""));
+    }
+
+    #[tokio::test]
+    async fn module_out_of_memory<M: RawModule + Send + Sync + 'static>()
+    where
+        M::Linker: Linker<M, Config = LinkerConfig>,
+    {
+        // Less than the initial memory of ayacript.
+        let ayacript = ModuleConfig {
+            limits: ModuleLimits {
+                memory: Some(64 * 1024),
+                table: None,
+            },
+            ..ModuleConfig::from("ayacript")
+        };
+        let err = load_modules::<M>(LinkerConfig::default(), vec![ayacript])
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("\"ayacript\""));
+    }
+
+    #[tokio::test]
+    async fn trap<M: RawModule + Send + Sync + 'static>()
+    where
+        M::Linker: Linker<M, Config = LinkerConfig>,
+    {
+        with_ctx::<M>(|ctx| {
+            // The args cannot be deserialized.
+            let err = ctx
+                .module
+                .call::<_, LineProcessResult>("exec", ("garbage",))
+                .err()
+                .unwrap();
+            let err = err.downcast_ref::<PluginError>().unwrap();
+            assert_eq!(err.plugin, "ayacript");
+            assert_eq!(err.export, "exec");
+            assert!(err.is_trap());
+            assert!(!ctx.module.is_disabled());
+            // Re-instantiated.
+            assert_eq!(ctx.call("$a = 1 + 1")["a"], RawValue::Num(2));
+            // The result is skipped only with other policies.
+            let res = ctx
+                .module
+                .call::<_, LineProcessResult>("exec", ("garbage",));
+            assert!(ctx.module.recover(res).is_err());
+        })
+        .await;
+    }
+
     #[instantiate_tests(<ayaka_plugin_wasmi::WasmiModule>)]
     mod inst_wasmi {}
     #[instantiate_tests(<ayaka_plugin_wasmtime::WasmtimeModule>)]
     mod inst_wasmtime {}
 }
+
+fn manifest(name: &str, deps: &[&str]) -> (String, PluginManifest) {
+    let manifest = deps
+        .iter()
+        .fold(PluginManifest::new(name, "0.1.0"), |manifest, dep| {
+            manifest.dependency(*dep)
+        });
+    (name.to_string(), manifest)
+}
+
+fn sort(manifests: &[(String, PluginManifest)]) -> anyhow::Result<Vec<&str>> {
+    let order = super::sort_plugins(
+        manifests
+            .iter()
+            .map(|(name, manifest)| (name.as_str(), manifest)),
+    )?;
+    Ok(order.into_iter().map(|i| manifests[i].0.as_str()).collect())
+}
+
+#[test]
+fn sort_plugins() {
+    let manifests = [
+        manifest("media", &["ayalog"]),
+        manifest("ayacript", &[]),
+        manifest("ayalog", &["random"]),
+        manifest("random", &[]),
+    ];
+    assert_eq!(
+        sort(&manifests).unwrap(),
+        ["ayacript", "random", "ayalog", "media"]
+    );
+}
+
+#[test]
+fn sort_plugins_missing() {
+    let manifests = [manifest("media", &["ayalog"])];
+    assert!(sort(&manifests).is_err());
+}
+
+#[test]
+fn sort_plugins_circular() {
+    let manifests = [
+        manifest("ayacript", &[]),
+        manifest("media", &["ayalog"]),
+        manifest("ayalog", &["media"]),
+    ];
+    assert!(sort(&manifests).is_err());
+}
+
+#[test]
+fn check_pipeline() {
+    let manifests = HashMap::from([
+        manifest("media", &["ayalog"]),
+        manifest("ayalog", &[]),
+        manifest("markdown", &[]),
+    ]);
+    let pipeline = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert!(super::check_pipeline(
+        &pipeline(&["ayalog", "markdown", "media"]),
+        &manifests,
+        "action"
+    )
+    .is_ok());
+    // The dependency not in the pipeline is not checked.
+    assert!(super::check_pipeline(&pipeline(&["media"]), &manifests, "action").is_ok());
+    assert!(super::check_pipeline(&pipeline(&["media", "ayalog"]), &manifests, "action").is_err());
+}
@@ -117,5 +117,11 @@ mod test {
     fn r#ref() {
         assert_eq!(RefParser::new().parse("a").unwrap(), Ref::Var("a".into()));
         assert_eq!(RefParser::new().parse("$a").unwrap(), Ref::Ctx("a".into()));
+        assert_eq!(RefParser::new().parse("$?").unwrap(), Ref::Ctx("?".into()));
+        assert_eq!(
+            RefParser::new().parse("$?timeout").unwrap(),
+            Ref::Ctx("?timeout".into())
+        );
+        assert!(RefParser::new().parse("$? timeout").is_err());
     }
 }